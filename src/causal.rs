@@ -0,0 +1,251 @@
+//! Causality: which stimulus each reaction answers.
+//!
+//! When an object holds its reactions back and releases them later, in a batch,
+//! the judge can't tell which stimulus a given reaction answers. Often, the object can.
+//! [`judge_causal`] tags every stimulus with a [`StimulusId`] and lets a [`CausalObject`]
+//! say which stimuli [`Caused`] each of its reactions. The judge still sees plain reactions;
+//! the causes go into the [`Transcript`], next to the reactions ([`Step::causes`]).
+//!
+//! From a transcript, [`Causality`] builds the graph from stimuli to the reactions
+//! they caused, and measures how long each request waited for its first answer.
+//!
+//! An object that doesn't know about causes can be wrapped in [`Immediate`],
+//! which attributes every reaction to the stimulus it's a reaction to.
+//!
+//! [`Step::causes`]: crate::trace::Step::causes
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use crate::trace::{self, Recorded, Transcript};
+use crate::{Config, Judge, Object, Simulation};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A stimulus, by the index of its step in the [`Transcript`]
+/// (which is also the number of calls made before it).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StimulusId(pub usize);
+
+/// A reaction, by its step in the [`Transcript`] and its index in that step's batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReactionId {
+    /// The index of the step.
+    pub step: usize,
+    /// The index of the reaction in the step's batch.
+    pub index: usize,
+}
+
+/// A reaction, and the stimuli that caused it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Caused<C> {
+    /// The reaction.
+    pub reaction: C,
+    /// The stimuli that caused it (none, if it came out of the blue).
+    pub by: Vec<StimulusId>,
+}
+
+impl<C> Caused<C> {
+    /// A reaction caused by a single stimulus.
+    pub fn by(reaction: C, id: StimulusId) -> Self {
+        Self {
+            reaction,
+            by: alloc::vec![id],
+        }
+    }
+}
+
+/// An object that says which stimuli caused its reactions.
+///
+/// It's implemented for every `FnMut(StimulusId, C) -> Vec<Caused<C>>` closure.
+/// For an object that doesn't know, see [`Immediate`].
+pub trait CausalObject<C> {
+    /// Like [`Object::react`], but the observation comes with its id,
+    /// and the reactions with their causes.
+    fn react_causal(&mut self, id: StimulusId, observation: C) -> Vec<Caused<C>>;
+}
+
+impl<C, F> CausalObject<C> for F
+where
+    F: FnMut(StimulusId, C) -> Vec<Caused<C>>,
+{
+    fn react_causal(&mut self, id: StimulusId, observation: C) -> Vec<Caused<C>> {
+        self(id, observation)
+    }
+}
+
+/// An [`Object`] whose reactions are all caused by the observation they're a reaction to.
+pub struct Immediate<O>(pub O);
+
+impl<C, O: Object<C>> CausalObject<C> for Immediate<O> {
+    fn react_causal(&mut self, id: StimulusId, observation: C) -> Vec<Caused<C>> {
+        let reactions = self.0.react(observation);
+        reactions.into_iter().map(|r| Caused::by(r, id)).collect()
+    }
+}
+
+/// Like [`judge_traced_with`](crate::trace::judge_traced_with), but with a [`CausalObject`],
+/// whose causes are recorded in the transcript.
+pub fn judge_causal<J, O>(config: Config, judge: J, object: O) -> Result<Recorded<J>, J::Error>
+where
+    J: Judge,
+    J::Change: Clone,
+    O: CausalObject<J::Change>,
+{
+    let mut sim = Simulation::<_, _, Infallible>::build(config, judge, object);
+    // The causes of each step's reactions; the next stimulus is the next step.
+    let mut causes = Vec::new();
+    let mut transcript = trace::record(&mut sim, |object, stimulus| {
+        let id = StimulusId(causes.len());
+        let caused = object.react_causal(id, stimulus);
+        let (reactions, by): (Vec<_>, Vec<_>) =
+            caused.into_iter().map(|c| (c.reaction, c.by)).unzip();
+        causes.push(by);
+        Ok(reactions)
+    })?;
+    for (step, causes) in transcript.steps.iter_mut().zip(causes) {
+        step.causes = causes;
+    }
+    Ok(Recorded {
+        exit: sim.into_exit().expect("over"),
+        transcript,
+    })
+}
+
+/// The causality graph of a run: which reactions each stimulus caused.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Causality {
+    /// The reactions each stimulus caused, in order (only stimuli that caused any).
+    effects: BTreeMap<StimulusId, Vec<ReactionId>>,
+}
+
+impl Causality {
+    /// The graph recorded in this transcript.
+    pub fn of<C>(transcript: &Transcript<C>) -> Self {
+        let mut effects: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (step, s) in transcript.steps.iter().enumerate() {
+            for (index, causes) in s.causes.iter().enumerate() {
+                for &id in causes {
+                    effects
+                        .entry(id)
+                        .or_default()
+                        .push(ReactionId { step, index });
+                }
+            }
+        }
+        Self { effects }
+    }
+
+    /// The reactions this stimulus caused, in order.
+    pub fn effects(&self, id: StimulusId) -> &[ReactionId] {
+        self.effects.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The stimuli that caused anything, in order.
+    pub fn causes(&self) -> impl Iterator<Item = StimulusId> + '_ {
+        self.effects.keys().copied()
+    }
+
+    /// How many calls this stimulus waited for its first answer
+    /// (zero if it was answered right away), or `None` if it never was.
+    pub fn latency(&self, id: StimulusId) -> Option<usize> {
+        let first = self.effects(id).first()?;
+        Some(first.step.saturating_sub(id.0))
+    }
+
+    /// The latency of every stimulus that was answered, in order.
+    pub fn latencies(&self) -> impl Iterator<Item = (StimulusId, usize)> + '_ {
+        self.causes().filter_map(|id| Some((id, self.latency(id)?)))
+    }
+
+    /// How many stimuli waited how long: latency (in calls) to count.
+    pub fn distribution(&self) -> BTreeMap<usize, usize> {
+        let mut counts = BTreeMap::new();
+        for (_, latency) in self.latencies() {
+            *counts.entry(latency).or_default() += 1;
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod test_causal {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::Judgment;
+    use core::mem;
+
+    /// Pops in pairs: holds on to the first value, and releases both with the second.
+    fn demo_impl_pairs() -> impl FnMut(StimulusId, StackChange) -> Vec<Caused<StackChange>> {
+        let mut stack = vec![];
+        let mut held = vec![];
+        move |id, msg| {
+            match msg {
+                Push(x) => stack.push(x),
+                Pop => held.push(Caused::by(Value(stack.pop()), id)),
+                Value(_) => panic!("Value in demo_impl"),
+            }
+            if held.len() == 2 {
+                mem::take(&mut held)
+            } else {
+                vec![]
+            }
+        }
+    }
+
+    #[test]
+    fn test_immediate() {
+        let mut stack = vec![];
+        let t = judge_causal(
+            Config::new(),
+            scenario_1(),
+            Immediate(demo_impl_good(&mut stack)),
+        )
+        .unwrap();
+        assert_eq!(t.exit.judged().unwrap().judgment, Judgment::Done);
+        assert_eq!(t.transcript.steps[3].causes, [[StimulusId(3)]]);
+        let graph = Causality::of(&t.transcript);
+        assert_eq!(
+            graph.causes().collect::<Vec<_>>(),
+            [StimulusId(3), StimulusId(4), StimulusId(6)]
+        );
+        assert_eq!(graph.distribution(), BTreeMap::from([(0, 3)]));
+    }
+
+    #[test]
+    fn test_buffered() {
+        // Push(1), Push(2), Push(3), Pop, Pop, Push(4), Pop: the first two pops
+        // come back together, and the last one never does.
+        let t = judge_causal(Config::new(), scenario_1(), demo_impl_pairs()).unwrap();
+        assert_eq!(t.exit.judged().unwrap().judgment, Judgment::Done);
+        assert_eq!(
+            t.transcript.steps[4].reactions,
+            [Value(Some(3)), Value(Some(2))]
+        );
+        let graph = Causality::of(&t.transcript);
+        assert_eq!(
+            graph.effects(StimulusId(3)),
+            [ReactionId { step: 4, index: 0 }]
+        );
+        assert_eq!(graph.latency(StimulusId(3)), Some(1));
+        assert_eq!(graph.latency(StimulusId(4)), Some(0));
+        assert_eq!(graph.latency(StimulusId(6)), None);
+        assert_eq!(graph.distribution(), BTreeMap::from([(0, 1), (1, 1)]));
+
+        // Out of budget, the causes recorded so far are still there.
+        let t = judge_causal(Config::new().max_calls(5), scenario_1(), demo_impl_pairs()).unwrap();
+        assert_eq!(t.exit.calls(), 5);
+        assert_eq!(Causality::of(&t.transcript).latency(StimulusId(3)), Some(1));
+
+        // Without causes, there's no graph.
+        let mut stack = vec![];
+        let t = crate::trace::judge_traced(scenario_1(), demo_impl_good(&mut stack)).unwrap();
+        assert_eq!(Causality::of(&t.transcript).causes().count(), 0);
+    }
+}
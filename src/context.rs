@@ -0,0 +1,283 @@
+//! What the runner tells the judge on every turn, besides the reactions.
+//!
+//! See [`Judge::next_with`](crate::Judge::next_with).
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::rng::Rng;
+use crate::trace::Note;
+
+/// The runner's side of a judge's turn.
+///
+/// Every runner in this crate owns one of these for the duration of a simulation
+/// and lends it to [`Judge::next_with`](crate::Judge::next_with) on every turn.
+/// With it, a judge doesn't need to keep its own counters:
+/// the runner already knows how many calls it has made.
+///
+/// ## Randomness
+///
+/// An adversarial judge should draw its randomness from [`rng`](StepContext::rng)
+/// instead of embedding its own generator. The runner seeds it from
+/// [`Config::seed`](crate::Config::seed) and records the seed in
+/// [`Outcome::seed`](crate::Outcome::seed), so a failing run can be reproduced
+/// exactly by running it again with the same seed.
+///
+/// ## Annotations
+///
+/// A judge can explain itself with [`annotate`](StepContext::annotate).
+/// Runners that record a [`Transcript`](crate::trace::Transcript)
+/// keep the notes in it, next to the steps they were made at; the others drop them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StepContext {
+    rng: Rng,
+    call: usize,
+    clock: u64,
+    keep_notes: bool,
+    notes: Vec<Note>,
+}
+
+impl StepContext {
+    /// A fresh context whose randomness starts from `seed`.
+    ///
+    /// Runners make their own; this is for calling a judge by hand (in a unit test, say).
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            call: 0,
+            clock: 0,
+            keep_notes: false,
+            notes: Vec::new(),
+        }
+    }
+
+    /// The runner's random number generator.
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// The seed the runner's random number generator started from.
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    /// Number of calls of the object so far.
+    ///
+    /// On the judge's first turn, it's zero; if the judge continues,
+    /// the stimulus it returns will be delivered in call number `call() + 1`.
+    pub fn call(&self) -> usize {
+        self.call
+    }
+
+    /// The logical clock: the number of events so far, where
+    /// every stimulus delivered and every reaction produced is one event.
+    ///
+    /// It only ever goes forward, and strictly so between turns that follow a call.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// Write down a note for the trace, attached to the current call.
+    pub fn annotate(&mut self, text: impl Into<String>) {
+        if self.keep_notes {
+            self.notes.push(Note {
+                call: self.call,
+                text: text.into(),
+            });
+        }
+    }
+
+    /// Whether notes are kept at all (by default, they aren't).
+    pub(crate) fn keep_notes(&mut self, keep: bool) {
+        self.keep_notes = keep;
+    }
+
+    /// The notes kept since the last time.
+    pub(crate) fn take_notes(&mut self) -> Vec<Note> {
+        core::mem::take(&mut self.notes)
+    }
+
+    /// Account for a call of the object that produced this many reactions.
+    pub(crate) fn called(&mut self, reactions: usize) {
+        self.call += 1;
+        self.clock += 1 + reactions as u64;
+    }
+}
+
+#[cfg(test)]
+mod test_context {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::trace::judge_traced;
+    use crate::{judge_with, Config, Judge, Judgment, Simulation};
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use core::convert::Infallible;
+
+    /// Pushes and pops at random, and checks the pops against a reference stack.
+    #[derive(Debug, Clone, PartialEq)]
+    struct Random {
+        left: usize,
+        stack: Vec<i32>,
+        expect: Option<Option<i32>>,
+    }
+
+    fn random(calls: usize) -> Random {
+        Random {
+            left: calls,
+            stack: Vec::new(),
+            expect: None,
+        }
+    }
+
+    impl Judge for Random {
+        type Change = StackChange;
+        type Fault = String;
+        type Error = Infallible;
+        fn next(
+            &mut self,
+            reactions: Vec<StackChange>,
+        ) -> Result<Judgment<StackChange, String>, Infallible> {
+            self.next_with(reactions, &mut StepContext::new(0))
+        }
+        fn next_with(
+            &mut self,
+            reactions: Vec<StackChange>,
+            cx: &mut StepContext,
+        ) -> Result<Judgment<StackChange, String>, Infallible> {
+            if let Some(expect) = self.expect.take() {
+                if reactions != [Value(expect)] {
+                    return Ok(Judgment::Fault(format!(
+                        "expected {expect:?}, got {reactions:?}"
+                    )));
+                }
+            } else if !reactions.is_empty() {
+                return Ok(Judgment::Fault("unexpected reactions".to_string()));
+            }
+            if self.left == 0 {
+                return Ok(Judgment::Done);
+            }
+            self.left -= 1;
+            Ok(Judgment::Continue(if cx.rng().chance(0.5) {
+                let x = cx.rng().below(100) as i32;
+                self.stack.push(x);
+                Push(x)
+            } else {
+                self.expect = Some(self.stack.pop());
+                Pop
+            }))
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_run() {
+        let run = |seed| {
+            let mut stack = vec![];
+            let config = Config::new().seed(seed);
+            let mut sim = Simulation::with_config(config, random(50), demo_impl_good(&mut stack));
+            let mut steps = Vec::new();
+            while let Some(step) = sim.step().unwrap() {
+                steps.push(step);
+            }
+            (steps, sim.into_exit().unwrap())
+        };
+        let (a, exit) = run(17);
+        assert_eq!((a.clone(), exit.clone()), run(17));
+        assert_eq!(exit.outcome().unwrap().seed, 17);
+        assert_eq!(exit.outcome().unwrap().judgment, Judgment::Done);
+        assert_ne!(a, run(18).0);
+    }
+
+    #[test]
+    fn test_default_seed() {
+        // Plain runners seed with zero.
+        let mut stack = vec![];
+        let t = judge_traced(random(20), demo_impl_good(&mut stack)).unwrap();
+        assert_eq!(t.outcome.seed, 0);
+        let mut stack = vec![];
+        let config = Config::new().seed(0);
+        let mut sim = Simulation::with_config(config, random(20), demo_impl_good(&mut stack));
+        for step in &t.transcript.steps {
+            assert_eq!(sim.step().unwrap().as_ref(), Some(step));
+        }
+    }
+
+    /// Pushes `0..n`, then pops once, counting with the context instead of itself.
+    struct Counted(usize);
+    impl Judge for Counted {
+        type Change = StackChange;
+        type Fault = String;
+        type Error = Infallible;
+        fn next(
+            &mut self,
+            reactions: Vec<StackChange>,
+        ) -> Result<Judgment<StackChange, String>, Infallible> {
+            self.next_with(reactions, &mut StepContext::new(0))
+        }
+        fn next_with(
+            &mut self,
+            reactions: Vec<StackChange>,
+            cx: &mut StepContext,
+        ) -> Result<Judgment<StackChange, String>, Infallible> {
+            let call = cx.call();
+            Ok(if call < self.0 {
+                Judgment::Continue(Push(call as i32))
+            } else if call == self.0 {
+                cx.annotate(format!("popping at clock {}", cx.clock()));
+                Judgment::Continue(Pop)
+            } else if reactions == [Value(Some(self.0 as i32 - 1))] {
+                cx.annotate("done");
+                Judgment::Done
+            } else {
+                Judgment::Fault(format!("bad pop: {reactions:?}"))
+            })
+        }
+    }
+
+    #[test]
+    fn test_call_clock_notes() {
+        let mut stack = vec![];
+        let t = judge_traced(Counted(3), demo_impl_good(&mut stack)).unwrap();
+        assert_eq!(t.outcome.judgment, Judgment::Done);
+        assert_eq!(t.outcome.calls, 4);
+        // Three pushes, no reactions: three ticks.
+        assert_eq!(
+            t.transcript.notes_at(3).collect::<Vec<_>>(),
+            ["popping at clock 3"]
+        );
+        assert_eq!(t.transcript.notes_at(4).collect::<Vec<_>>(), ["done"]);
+        assert_eq!(t.transcript.notes.len(), 2);
+
+        // Runners that don't record drop the notes, and old-style calls still work.
+        let mut stack = vec![];
+        let mut sim = Simulation::new(Counted(3), demo_impl_good(&mut stack));
+        sim.run_to_end().unwrap();
+        assert!(sim.take_notes().is_empty());
+        assert_eq!(sim.context().clock(), 5);
+        assert_eq!(sim.context().call(), 4);
+        let mut j = Counted(0);
+        assert_eq!(j.next(vec![]), Ok(Judgment::Continue(Pop)));
+    }
+
+    #[test]
+    fn test_seed_finds_bug() {
+        // Some seeds catch the stack that only remembers how many it holds;
+        // the outcome says which.
+        let faulty = (0..20u64).find_map(|seed| {
+            let mut count = 0;
+            let config = Config::new().seed(seed);
+            let object = demo_impl_zero_smart(&mut count);
+            let exit = judge_with(config, random(10), object).unwrap();
+            let o = exit.judged().unwrap();
+            matches!(o.judgment, Judgment::Fault(_)).then_some(o.seed)
+        });
+        let seed = faulty.expect("some seed finds it");
+        let mut count = 0;
+        let object = demo_impl_zero_smart(&mut count);
+        let again = judge_with(Config::new().seed(seed), random(10), object);
+        assert!(matches!(
+            again.unwrap().judged().unwrap().judgment,
+            Judgment::Fault(_)
+        ));
+    }
+}
@@ -0,0 +1,450 @@
+//! Deadlines: reactions that must arrive within so many calls, or so much time.
+//!
+//! A common judgment is "the response to this request must come within `k` calls".
+//! `StackJudge`, in the tests of this crate, approximates it with a queue of
+//! expected values, and placeholders for the ones that are late.
+//! [`Deadlines`] does the bookkeeping instead: the judge registers an obligation
+//! whenever it sends a stimulus that calls for a response, and hands every batch
+//! of reactions to [`observe`](Deadlines::observe). A reaction that fulfills an
+//! obligation discharges it; an obligation that runs out of calls (or of
+//! [virtual time](crate::time)) comes back as a [`Missed`], ready to be a fault.
+//!
+//! Calls only come with reactions, but time passes without any: a judge that sets
+//! time deadlines should schedule a wakeup just past the [`next_deadline`](Deadlines::next_deadline),
+//! so that an obligation nobody answers is noticed when it expires, not whenever
+//! the next stimulus happens to come.
+//!
+//! ```
+//! use caet::deadline::{Deadlines, Within};
+//!
+//! let mut deadlines: Deadlines<&str> = Deadlines::new();
+//! // Before the first call, ask twice; each answer is due within 2 calls.
+//! deadlines.expect("ping", "pong", Within::Calls(2));
+//! deadlines.expect("ping", "pong", Within::Calls(2));
+//! // Call 1: one answer, and some chatter the deadlines don't care about.
+//! assert_eq!(deadlines.observe(vec!["pong", "hello"], 1, 0), Ok(vec!["hello"]));
+//! // Call 2: nothing; the second answer is late.
+//! let missed = deadlines.observe(vec![], 2, 0).unwrap_err();
+//! assert_eq!(
+//!     missed.to_string(),
+//!     "expected \"pong\" for \"ping\" within 2 calls (due by call 2)"
+//! );
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display};
+
+use crate::matcher::{Captures, Matcher};
+use crate::time::Time;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How long an obligation lasts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Within {
+    /// The response must be among the reactions to the next so many calls
+    /// (at least one; `Calls(1)` means right away).
+    Calls(usize),
+    /// The response must arrive within so much virtual time.
+    Time(Time),
+}
+
+impl Display for Within {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Within::Calls(1) => write!(f, "1 call"),
+            Within::Calls(n) => write!(f, "{n} calls"),
+            Within::Time(t) => write!(f, "time {t}"),
+        }
+    }
+}
+
+/// When an obligation is due: the [`Within`] of the obligation, counted from
+/// the call and time at which it was registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Due {
+    /// The last call whose reactions may fulfill it.
+    Call(usize),
+    /// The latest time at which it may be fulfilled.
+    Time(Time),
+}
+
+impl Display for Due {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Due::Call(call) => write!(f, "call {call}"),
+            Due::Time(time) => write!(f, "time {time}"),
+        }
+    }
+}
+
+/// An obligation that expired before a reaction fulfilled it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Missed<C> {
+    /// The stimulus that called for a response.
+    pub stimulus: C,
+    /// What the response should have been.
+    pub expected: String,
+    /// How long it had.
+    pub within: Within,
+    /// When it was due.
+    pub due: Due,
+}
+
+impl<C: Debug> Display for Missed<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected {} for {:?} within {} (due by {})",
+            self.expected, self.stimulus, self.within, self.due
+        )
+    }
+}
+
+/// A response the judge is owed.
+#[derive(Debug)]
+struct Obligation<C, V> {
+    stimulus: C,
+    expect: Matcher<C, V>,
+    within: Within,
+    due: Due,
+}
+
+impl<C, V> Obligation<C, V> {
+    fn expired(&self, call: usize, now: Time) -> bool {
+        match self.due {
+            Due::Call(due) => call >= due,
+            Due::Time(due) => now > due,
+        }
+    }
+}
+
+/// The obligations a judge is tracking.
+///
+/// `C` is the change type; `V` is the type of the values its matchers
+/// [capture](Matcher::capture).
+#[derive(Debug)]
+pub struct Deadlines<C, V = C> {
+    /// Outstanding obligations, oldest first.
+    owed: Vec<Obligation<C, V>>,
+    /// What the matchers have captured so far.
+    captures: Captures<V>,
+    /// The call and time of the last observation.
+    at: (usize, Time),
+}
+
+impl<C, V> Default for Deadlines<C, V> {
+    fn default() -> Self {
+        Self {
+            owed: Vec::new(),
+            captures: Captures::new(),
+            at: (0, 0),
+        }
+    }
+}
+
+impl<C, V> Deadlines<C, V> {
+    /// No obligations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect a response to this stimulus, within this deadline, and say when it's due.
+    ///
+    /// The deadline counts from the last [`observe`](Self::observe),
+    /// so register the obligation just before sending the stimulus.
+    pub fn expect(
+        &mut self,
+        stimulus: C,
+        response: impl Into<Matcher<C, V>>,
+        within: Within,
+    ) -> Due {
+        let (call, now) = self.at;
+        let due = match within {
+            Within::Calls(n) => Due::Call(call.saturating_add(n)),
+            Within::Time(t) => Due::Time(now.saturating_add(t)),
+        };
+        self.owed.push(Obligation {
+            stimulus,
+            expect: response.into(),
+            within,
+            due,
+        });
+        due
+    }
+
+    /// The earliest time at which an outstanding obligation is due, if any has a time deadline.
+    ///
+    /// It's missed if nothing fulfills it by then, even if nothing happens at all;
+    /// but it's only noticed by the next [`observe`](Self::observe). To have one then,
+    /// schedule a stimulus on the [`Clock`](crate::time::Clock) just after this time.
+    pub fn next_deadline(&self) -> Option<Time> {
+        self.owed
+            .iter()
+            .filter_map(|o| match o.due {
+                Due::Time(due) => Some(due),
+                Due::Call(_) => None,
+            })
+            .min()
+    }
+
+    /// Number of outstanding obligations.
+    pub fn owed(&self) -> usize {
+        self.owed.len()
+    }
+
+    /// What the matchers have captured so far.
+    pub fn captures(&self) -> &Captures<V> {
+        &self.captures
+    }
+}
+
+impl<C: Debug + PartialEq, V: Clone> Deadlines<C, V> {
+    /// Take the reactions to a call, and check the deadlines.
+    ///
+    /// `call` is the number of calls made so far ([`StepContext::call`](crate::StepContext::call)),
+    /// and `now` the current time ([`Clock::now`](crate::time::Clock::now), or zero, without a clock).
+    ///
+    /// Each reaction discharges the oldest obligation it fulfills. Returns the reactions
+    /// that didn't fulfill any, or the oldest obligation that has expired.
+    pub fn observe(
+        &mut self,
+        reactions: Vec<C>,
+        call: usize,
+        now: Time,
+    ) -> Result<Vec<C>, Missed<C>> {
+        self.at = (call, now);
+        let mut rest = Vec::new();
+        for reaction in reactions {
+            let captures = &mut self.captures;
+            match self
+                .owed
+                .iter()
+                .position(|o| o.expect.matches(&reaction, captures))
+            {
+                Some(i) => {
+                    self.owed.remove(i);
+                }
+                None => rest.push(reaction),
+            }
+        }
+        match self.owed.iter().position(|o| o.expired(call, now)) {
+            Some(i) => Err(self.miss(i)),
+            None => Ok(rest),
+        }
+    }
+
+    /// The simulation is over: every outstanding obligation is missed.
+    ///
+    /// Returns the oldest, if there are any.
+    pub fn finish(&mut self) -> Result<(), Missed<C>> {
+        if self.owed.is_empty() {
+            Ok(())
+        } else {
+            Err(self.miss(0))
+        }
+    }
+
+    fn miss(&mut self, i: usize) -> Missed<C> {
+        let o = self.owed.remove(i);
+        Missed {
+            expected: o.expect.describe(&self.captures),
+            stimulus: o.stimulus,
+            within: o.within,
+            due: o.due,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_deadline {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::time::{judge_timed, Clock, TimedJudge, Untimed};
+    use crate::{judge_object, Config, Judge, Judgment, StepContext};
+    use alloc::collections::VecDeque;
+    use alloc::string::ToString;
+    use core::convert::Infallible;
+
+    /// `StackJudge`, with a deadline for every pop instead of placeholders.
+    struct DeadlineJudge {
+        scenario: VecDeque<StackChange>,
+        ref_impl: Vec<i32>,
+        within: Within,
+        deadlines: Deadlines<StackChange>,
+    }
+    impl DeadlineJudge {
+        fn new(scenario: StackJudge, within: Within) -> Self {
+            Self {
+                scenario: scenario.scenario,
+                ref_impl: vec![],
+                within,
+                deadlines: Deadlines::new(),
+            }
+        }
+    }
+    impl Judge for DeadlineJudge {
+        type Change = StackChange;
+        type Fault = Missed<StackChange>;
+        type Error = Infallible;
+        fn next(
+            &mut self,
+            reactions: Vec<StackChange>,
+        ) -> Result<Judgment<StackChange, Self::Fault>, Infallible> {
+            self.next_with(reactions, &mut StepContext::new(0))
+        }
+        fn next_with(
+            &mut self,
+            reactions: Vec<StackChange>,
+            cx: &mut StepContext,
+        ) -> Result<Judgment<StackChange, Self::Fault>, Infallible> {
+            if let Err(missed) = self.deadlines.observe(reactions, cx.call(), 0) {
+                return Ok(Judgment::Fault(missed));
+            }
+            let Some(act) = self.scenario.pop_front() else {
+                return Ok(match self.deadlines.finish() {
+                    Ok(()) => Judgment::Done,
+                    Err(missed) => Judgment::Fault(missed),
+                });
+            };
+            match act {
+                Push(x) => self.ref_impl.push(x),
+                _ => {
+                    let value = self.ref_impl.pop();
+                    self.deadlines.expect(act, Value(value), self.within);
+                }
+            }
+            Ok(Judgment::Continue(act))
+        }
+    }
+
+    #[test]
+    fn test_on_time() {
+        let mut stack = vec![];
+        let judge = DeadlineJudge::new(scenario_1(), Within::Calls(1));
+        let o = judge_object(judge, demo_impl_good(&mut stack)).unwrap();
+        assert_eq!((o.judgment, o.calls), (Judgment::Done, 7));
+    }
+
+    #[test]
+    fn test_late() {
+        // The first pop is the fourth call; nothing ever comes back.
+        let judge = DeadlineJudge::new(scenario_1(), Within::Calls(2));
+        let o = judge_object(judge, demo_impl_discard()).unwrap();
+        let Judgment::Fault(missed) = o.judgment else {
+            panic!("not a fault: {:?}", o.judgment);
+        };
+        assert_eq!(
+            missed.to_string(),
+            "expected Value(Some(3)) for Pop within 2 calls (due by call 5)"
+        );
+
+        // Too late for the first pop, with calls to spare: the scenario is over.
+        let judge = DeadlineJudge::new(scenario_1(), Within::Calls(5));
+        let o = judge_object(judge, demo_impl_discard()).unwrap();
+        let Judgment::Fault(missed) = o.judgment else {
+            panic!("not a fault: {:?}", o.judgment);
+        };
+        assert_eq!(
+            (missed.expected.as_str(), missed.due),
+            ("Value(Some(3))", Due::Call(8))
+        );
+    }
+
+    #[test]
+    fn test_time() {
+        let mut deadlines: Deadlines<&str> = Deadlines::new();
+        let due = deadlines.expect("req", Matcher::any(), Within::Time(10));
+        assert_eq!((due, deadlines.next_deadline()), (Due::Time(10), Some(10)));
+        assert_eq!(deadlines.observe(vec![], 1, 10), Ok(vec![]));
+        let missed = deadlines.observe(vec![], 2, 11).unwrap_err();
+        assert_eq!(
+            missed.to_string(),
+            "expected anything for \"req\" within time 10 (due by time 10)"
+        );
+        assert_eq!(deadlines.owed(), 0);
+        assert_eq!(deadlines.next_deadline(), None);
+        assert_eq!(deadlines.finish(), Ok(()));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Net {
+        Request,
+        Response,
+        /// The judge's alarm clock.
+        Wakeup,
+    }
+
+    /// Sends a request at time 0, which must be answered within 10, and schedules
+    /// another at time 100, which nobody waits for: it only moves the clock along,
+    /// so the first deadline is found missed then. Optionally, sets its alarm
+    /// just past the deadline, to find it missed sooner.
+    struct Impatient {
+        alarm: bool,
+        sent: bool,
+        deadlines: Deadlines<Net>,
+    }
+    impl Judge for Impatient {
+        type Change = Net;
+        type Fault = Missed<Net>;
+        type Error = Infallible;
+        fn next(&mut self, reactions: Vec<Net>) -> Result<Judgment<Net, Self::Fault>, Infallible> {
+            self.next_timed(reactions, &mut StepContext::new(0), &mut Clock::new())
+        }
+    }
+    impl TimedJudge for Impatient {
+        fn next_timed(
+            &mut self,
+            reactions: Vec<Net>,
+            cx: &mut StepContext,
+            clock: &mut Clock<Net>,
+        ) -> Result<Judgment<Net, Self::Fault>, Infallible> {
+            if let Err(missed) = self.deadlines.observe(reactions, cx.call(), clock.now()) {
+                return Ok(Judgment::Fault(missed));
+            }
+            if !self.sent {
+                self.sent = true;
+                clock.schedule(100, Net::Request);
+                self.deadlines
+                    .expect(Net::Request, Net::Response, Within::Time(10));
+                if self.alarm {
+                    let due = self.deadlines.next_deadline().unwrap();
+                    clock.schedule(due + 1, Net::Wakeup);
+                }
+                return Ok(Judgment::Continue(Net::Request));
+            }
+            Ok(Judgment::Done)
+        }
+    }
+
+    #[test]
+    fn test_silence() {
+        // Nobody ever answers, and nothing happens until time 100.
+        let run = |alarm| {
+            let judge = Impatient {
+                alarm,
+                sent: false,
+                deadlines: Deadlines::new(),
+            };
+            let silent = Untimed(|_: Net| vec![]);
+            judge_timed(Config::new(), judge, silent)
+                .unwrap()
+                .judged()
+                .unwrap()
+        };
+        let late = run(false);
+        let early = run(true);
+        assert_eq!((late.time, early.time), (100, 11));
+        for o in [late, early] {
+            let Judgment::Fault(missed) = o.judgment else {
+                panic!("not a fault: {:?}", o.judgment);
+            };
+            assert_eq!(missed.due, Due::Time(10));
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Discrete-event simulation: the event queue under the [`Clock`], and arrival processes.
+//!
+//! An [`Agenda`] is a priority queue of stimuli, by time. Events due at the same time
+//! come out in the order they were first scheduled, so a run is deterministic.
+//! Every event gets an [`EventId`], with which a judge (or an object, for its timers)
+//! can cancel or reschedule it later. The [`Clock`] keeps one.
+//!
+//! [`Arrivals`] describes when stimuli arrive from the outside world:
+//! on a fixed schedule, periodically, at random (a Poisson process), or in bursts.
+//! [`Arrivals::schedule`] puts them on the clock, so a judge only has to say
+//! what arrives, not to keep track of when.
+//!
+//! ```
+//! use caet::des::Arrivals;
+//! use caet::rng::Rng;
+//! use caet::time::Clock;
+//!
+//! let mut clock = Clock::new();
+//! let ids = Arrivals::Periodic { every: 10 }.schedule(&mut clock, 35, &mut Rng::new(0), |n| n);
+//! assert_eq!(ids.len(), 4);
+//! // The third one doesn't arrive after all; the fourth one comes early.
+//! assert_eq!(clock.cancel(ids[2]), Some(2));
+//! assert!(clock.reschedule(ids[3], 5));
+//! assert_eq!(clock.next_time(), Some(0));
+//! let agenda: Vec<_> = clock.agenda().iter().map(|(at, _, &n)| (at, n)).collect();
+//! assert_eq!(agenda, [(0, 0), (5, 3), (10, 1)]);
+//! ```
+//!
+//! [`Clock`]: crate::time::Clock
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::rng::Rng;
+use crate::time::{Clock, Time};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// An event on an [`Agenda`].
+///
+/// Ids are handed out in the order events are scheduled, and never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EventId(u64);
+
+/// A priority queue of stimuli, by time.
+///
+/// Events due at the same time come out in the order they were scheduled
+/// (rescheduling an event doesn't change its place among those).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Agenda<C> {
+    /// The events, by time, then by id.
+    events: BTreeMap<(Time, EventId), C>,
+    /// When each event is due.
+    due: BTreeMap<EventId, Time>,
+    /// The id of the next event.
+    next: u64,
+}
+
+impl<C> Default for Agenda<C> {
+    fn default() -> Self {
+        Self {
+            events: BTreeMap::new(),
+            due: BTreeMap::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<C> Agenda<C> {
+    /// An empty agenda.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add this event, due at this time.
+    pub fn schedule(&mut self, at: Time, event: C) -> EventId {
+        let id = EventId(self.next);
+        self.next += 1;
+        self.events.insert((at, id), event);
+        self.due.insert(id, at);
+        id
+    }
+
+    /// Remove this event, if it's still on the agenda.
+    pub fn cancel(&mut self, id: EventId) -> Option<C> {
+        let at = self.due.remove(&id)?;
+        self.events.remove(&(at, id))
+    }
+
+    /// Move this event to another time.
+    ///
+    /// Returns `false` if it's no longer on the agenda.
+    pub fn reschedule(&mut self, id: EventId, at: Time) -> bool {
+        let Some(due) = self.due.get_mut(&id) else {
+            return false;
+        };
+        let event = self.events.remove(&(*due, id)).expect("indexed");
+        *due = at;
+        self.events.insert((at, id), event);
+        true
+    }
+
+    /// When this event is due, if it's still on the agenda.
+    pub fn due(&self, id: EventId) -> Option<Time> {
+        self.due.get(&id).copied()
+    }
+
+    /// The next event, without removing it.
+    pub fn peek(&self) -> Option<(Time, EventId, &C)> {
+        self.iter().next()
+    }
+
+    /// Remove the next event.
+    pub fn pop(&mut self) -> Option<(Time, EventId, C)> {
+        let ((at, id), event) = self.events.pop_first()?;
+        self.due.remove(&id);
+        Some((at, id, event))
+    }
+
+    /// Every event, in the order they'll come out.
+    pub fn iter(&self) -> impl Iterator<Item = (Time, EventId, &C)> {
+        self.events.iter().map(|(&(at, id), event)| (at, id, event))
+    }
+
+    /// Number of events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether there are no events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Remove every event.
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.due.clear();
+    }
+}
+
+/// When stimuli arrive from the outside world.
+///
+/// Time is discrete, so the random processes are their discrete-time counterparts:
+/// on every tick, something arrives with probability `1 / mean`. The gaps between
+/// arrivals are drawn directly, so drawing them takes time proportional to
+/// the number of arrivals, not to the length of the interval.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Arrivals {
+    /// At these times (those in the past arrive right away).
+    Scheduled(Vec<Time>),
+    /// Now, and then every so often.
+    Periodic {
+        /// The time between arrivals. Must not be zero.
+        every: Time,
+    },
+    /// At random, independently of each other (a Poisson process).
+    Poisson {
+        /// The mean time between arrivals. Must not be zero.
+        mean: Time,
+    },
+    /// In bursts of simultaneous arrivals; the bursts arrive at random.
+    Bursty {
+        /// Arrivals per burst.
+        size: usize,
+        /// The mean time between bursts. Must not be zero.
+        mean: Time,
+    },
+}
+
+impl Arrivals {
+    /// The arrival times from `start` up to `until` (inclusive), in order.
+    ///
+    /// Only the random processes use `rng`.
+    pub fn times(&self, start: Time, until: Time, rng: &mut Rng) -> Vec<Time> {
+        match *self {
+            Arrivals::Scheduled(ref at) => {
+                let mut times: Vec<_> = at
+                    .iter()
+                    .map(|&t| t.max(start))
+                    .filter(|&t| t <= until)
+                    .collect();
+                times.sort();
+                times
+            }
+            Arrivals::Periodic { every } => {
+                assert!(every > 0, "zero period");
+                let mut times = Vec::new();
+                let mut t = Some(start);
+                while let Some(at) = t.filter(|&at| at <= until) {
+                    times.push(at);
+                    t = at.checked_add(every);
+                }
+                times
+            }
+            Arrivals::Poisson { mean } => Self::bursts(start, until, 1, mean, rng),
+            Arrivals::Bursty { size, mean } => Self::bursts(start, until, size, mean, rng),
+        }
+    }
+
+    /// Put the arrivals from now up to `until` on the clock.
+    ///
+    /// `stimulus` makes the `n`th one (counting from zero).
+    /// Returns their ids, in order of arrival.
+    pub fn schedule<C>(
+        &self,
+        clock: &mut Clock<C>,
+        until: Time,
+        rng: &mut Rng,
+        mut stimulus: impl FnMut(usize) -> C,
+    ) -> Vec<EventId> {
+        self.times(clock.now(), until, rng)
+            .into_iter()
+            .enumerate()
+            .map(|(n, at)| clock.schedule(at, stimulus(n)))
+            .collect()
+    }
+
+    /// Bursts of `size` arrivals, from `start` up to `until`: on every tick,
+    /// one arrives with probability `1 / mean`.
+    fn bursts(start: Time, until: Time, size: usize, mean: Time, rng: &mut Rng) -> Vec<Time> {
+        assert!(mean > 0, "zero mean");
+        // The number of ticks until the next burst is geometric: by inversion,
+        // `ln(u) / ln(1 - p)` for `u` uniform in `(0, 1]`.
+        let p = 1.0 / mean as f64;
+        // (`1 - p` would round a small `p` away.)
+        let ln_miss = if p <= 0.5 {
+            -two_atanh(p / (2.0 - p))
+        } else {
+            ln(1.0 - p)
+        };
+        let mut times = Vec::new();
+        let mut t = start;
+        loop {
+            // (The cast saturates, and a gap that large is past `until` anyway.)
+            let gap = (ln(1.0 - rng.unit()) / ln_miss) as Time;
+            let Some(at) = t.checked_add(gap).filter(|&at| at <= until) else {
+                return times;
+            };
+            times.resize(times.len() + size, at);
+            match at.checked_add(1) {
+                Some(next) => t = next,
+                None => return times,
+            }
+        }
+    }
+}
+
+/// The natural logarithm of `x`, in `[0, 1]` (`core` has none without `std`).
+fn ln(x: f64) -> f64 {
+    if x <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    // `x = m * 2^e`, with `m` in `[1, 2)` (`x` is normal: it's at least `2^-53`, or one).
+    let bits = x.to_bits();
+    let e = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let m = f64::from_bits((bits & ((1 << 52) - 1)) | (1023 << 52));
+    e as f64 * core::f64::consts::LN_2 + two_atanh((m - 1.0) / (m + 1.0))
+}
+
+/// `2 atanh(s)`, which is `ln((1 + s) / (1 - s))`, for `s` in `[0, 1/3]`.
+fn two_atanh(s: f64) -> f64 {
+    let (s2, mut term, mut sum) = (s * s, s, 0.0);
+    for k in 0..20 {
+        sum += term / (2 * k + 1) as f64;
+        term *= s2;
+    }
+    2.0 * sum
+}
+
+#[cfg(test)]
+mod test_des {
+    use super::*;
+    use crate::time::{judge_timed, TimedJudge};
+    use crate::{Config, Judge, Judgment, StepContext};
+    use core::convert::Infallible;
+
+    #[test]
+    fn test_agenda() {
+        let mut agenda = Agenda::new();
+        let b = agenda.schedule(5, 'b');
+        let a = agenda.schedule(3, 'a');
+        let c = agenda.schedule(5, 'c');
+        let d = agenda.schedule(1, 'd');
+        assert_eq!(agenda.peek(), Some((1, d, &'d')));
+        assert_eq!(agenda.cancel(d), Some('d'));
+        assert_eq!(agenda.cancel(d), None);
+        // Ties go by order of scheduling, even after rescheduling.
+        assert!(agenda.reschedule(b, 3));
+        assert!(agenda.reschedule(c, 3));
+        assert_eq!(agenda.due(c), Some(3));
+        let order: Vec<_> = core::iter::from_fn(|| agenda.pop()).collect();
+        assert_eq!(order, [(3, b, 'b'), (3, a, 'a'), (3, c, 'c')]);
+        assert!(!agenda.reschedule(a, 7));
+        assert!(agenda.is_empty());
+    }
+
+    #[test]
+    fn test_arrivals() {
+        let mut rng = Rng::new(3);
+        assert_eq!(
+            Arrivals::Scheduled(vec![9, 2, 20, 4]).times(3, 10, &mut rng),
+            [3, 4, 9]
+        );
+        assert_eq!(
+            Arrivals::Periodic { every: 4 }.times(1, 10, &mut rng),
+            [1, 5, 9]
+        );
+
+        let poisson = Arrivals::Poisson { mean: 10 };
+        let times = poisson.times(0, 99_999, &mut rng);
+        assert!(times.windows(2).all(|w| w[0] < w[1]));
+        // About one every 10 ticks.
+        assert!((9_000..11_000).contains(&times.len()), "{}", times.len());
+        // The same seed, the same arrivals.
+        assert_eq!(
+            poisson.times(0, 1000, &mut Rng::new(5)),
+            poisson.times(0, 1000, &mut Rng::new(5))
+        );
+
+        // Drawing them doesn't take ticks, but arrivals: here, about a thousand.
+        let sparse = Arrivals::Poisson { mean: 1 << 40 }.times(0, 1 << 50, &mut rng);
+        assert!((900..1100).contains(&sparse.len()), "{}", sparse.len());
+        // Nothing, in practice, with the longest mean there is.
+        let never = Arrivals::Poisson { mean: Time::MAX }.times(0, 1000, &mut rng);
+        assert!(never.is_empty());
+        // Something arrives on every tick, if that's the mean.
+        let dense = Arrivals::Poisson { mean: 1 }.times(5, 9, &mut rng);
+        assert_eq!(dense, [5, 6, 7, 8, 9]);
+
+        let times = Arrivals::Bursty { size: 3, mean: 50 }.times(0, 999, &mut rng);
+        assert_eq!(times.len() % 3, 0);
+        assert!(times.chunks(3).all(|b| b[0] == b[2]));
+        assert!(times
+            .chunks(3)
+            .zip(times.chunks(3).skip(1))
+            .all(|(a, b)| a[0] < b[0]));
+    }
+
+    #[test]
+    fn test_ln() {
+        for x in [1e-16, 0.001, 0.1, 0.5, 0.75, 1.0] {
+            assert!((ln(x) - f64::ln(x)).abs() < 1e-12, "{x}");
+        }
+        assert_eq!(ln(0.0), f64::NEG_INFINITY);
+    }
+
+    /// Customers arrive at random; the judge sends some of them away before they're served.
+    #[derive(Debug, PartialEq)]
+    struct Shop {
+        begun: bool,
+    }
+    impl Judge for Shop {
+        type Change = usize;
+        type Fault = usize;
+        type Error = Infallible;
+        fn next(&mut self, reactions: Vec<usize>) -> Result<Judgment<usize, usize>, Infallible> {
+            self.next_timed(reactions, &mut StepContext::new(0), &mut Clock::new())
+        }
+    }
+    impl TimedJudge for Shop {
+        fn next_timed(
+            &mut self,
+            _: Vec<usize>,
+            cx: &mut StepContext,
+            clock: &mut Clock<usize>,
+        ) -> Result<Judgment<usize, usize>, Infallible> {
+            if !self.begun {
+                self.begun = true;
+                let ids = Arrivals::Poisson { mean: 5 }.schedule(clock, 500, cx.rng(), |n| n);
+                // Every other customer leaves; the second one is kept waiting.
+                for &id in ids.iter().step_by(2) {
+                    clock.cancel(id);
+                }
+                assert!(clock.reschedule(ids[1], 1000));
+            }
+            Ok(Judgment::Done)
+        }
+    }
+
+    #[test]
+    fn test_judge_timed() {
+        let mut served = Vec::new();
+        let exit = judge_timed(
+            Config::new().seed(11),
+            Shop { begun: false },
+            crate::time::Untimed(|n: usize| {
+                served.push(n);
+                vec![]
+            }),
+        )
+        .unwrap();
+        let o = exit.judged().unwrap();
+        let (last, rest) = served.split_last().unwrap();
+        assert_eq!(*last, 1);
+        assert!(rest.iter().all(|n| n % 2 == 1));
+        assert!(rest.windows(2).all(|w| w[0] < w[1]));
+        assert!(served.len() > 10);
+        assert_eq!((o.calls, o.time), (served.len(), 1000));
+    }
+}
@@ -0,0 +1,202 @@
+//! Differential testing: two objects, one stream of stimuli.
+//!
+//! When there's an old and a new implementation of the same object,
+//! the quickest way to find out where they disagree is to run them side by side.
+//! [`judge_differential`] sends every stimulus the judge makes to both, in lockstep,
+//! and stops at the first call where their reactions differ, as if the pair had crashed
+//! with a [`Divergence`].
+//! The judge sees the reactions only once (they're the same, after all).
+//!
+//! If some differences don't matter (the order of a batch, say),
+//! [`judge_differential_with`] lets an arbiter decide whether the two agree,
+//! and what the judge gets to see when they do.
+
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display};
+
+use crate::{Config, Exit, Judge, Object, Simulation};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The first call where the two objects reacted differently.
+///
+/// It's the error of the [`Exit::Crashed`] that ends a differential run.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Divergence<C> {
+    /// The call (one-based, so it's also the number of calls made).
+    pub call: usize,
+    /// The stimulus both objects were given.
+    pub stimulus: C,
+    /// The first object's reactions.
+    pub first: Vec<C>,
+    /// The second object's reactions.
+    pub second: Vec<C>,
+}
+
+impl<C: Debug> Display for Divergence<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "diverged at call {} (stimulus {:?}): first {:?}, second {:?}",
+            self.call, self.stimulus, self.first, self.second
+        )
+    }
+}
+
+/// Like [`judge_with`](crate::judge_with), but with two objects, which
+/// must react exactly alike.
+///
+/// The simulation ends with [`Exit::Crashed`], with a [`Divergence`],
+/// at the first call where they don't; otherwise, as usual.
+pub fn judge_differential<J, A, B>(
+    config: Config,
+    judge: J,
+    first: A,
+    second: B,
+) -> Result<Exit<J, Divergence<J::Change>>, J::Error>
+where
+    J: Judge,
+    J::Change: Clone + PartialEq,
+    A: Object<J::Change>,
+    B: Object<J::Change>,
+{
+    judge_differential_with(config, judge, first, second, |_, a, b| {
+        (a == b).then(|| a.to_vec())
+    })
+}
+
+/// Like [`judge_differential`], but `arbiter` decides whether the objects agree.
+///
+/// It's given the stimulus and both objects' reactions, and returns the reactions
+/// the judge should see, or `None` if the objects diverged. To show the judge
+/// only the first object's reactions, for instance, return those.
+pub fn judge_differential_with<J, A, B>(
+    config: Config,
+    judge: J,
+    first: A,
+    second: B,
+    mut arbiter: impl FnMut(&J::Change, &[J::Change], &[J::Change]) -> Option<Vec<J::Change>>,
+) -> Result<Exit<J, Divergence<J::Change>>, J::Error>
+where
+    J: Judge,
+    J::Change: Clone,
+    A: Object<J::Change>,
+    B: Object<J::Change>,
+{
+    let mut sim = Simulation::build(config, judge, (first, second));
+    loop {
+        let call = sim.calls() + 1;
+        let react = |(first, second): &mut (A, B), stimulus: J::Change| {
+            let a = first.react(stimulus.clone());
+            let b = second.react(stimulus.clone());
+            arbiter(&stimulus, &a, &b).ok_or(Divergence {
+                call,
+                stimulus,
+                first: a,
+                second: b,
+            })
+        };
+        if sim.advance_by(|_| (), react)?.is_none() {
+            return Ok(sim.into_exit().expect("over"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_differential {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::Judgment;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_agree() {
+        let (mut a, mut b) = (vec![], vec![]);
+        let exit = judge_differential(
+            Config::new(),
+            scenario_1(),
+            demo_impl_good(&mut a),
+            demo_impl_good(&mut b),
+        )
+        .unwrap();
+        let o = exit.judged().unwrap();
+        assert_eq!((o.judgment, o.calls), (Judgment::Done, 7));
+
+        // Agreeing costs calls, too.
+        let (mut a, mut b) = (vec![], vec![]);
+        let config = Config::new().max_calls(3);
+        let exit = judge_differential(
+            config,
+            scenario_1(),
+            demo_impl_good(&mut a),
+            demo_impl_good(&mut b),
+        )
+        .unwrap();
+        assert_eq!(
+            exit,
+            Exit::Exhausted {
+                limit: crate::Limit::Calls,
+                calls: 3,
+                reactions: 0,
+                time: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_diverge() {
+        let mut stack = vec![];
+        let mut count = 0;
+        let exit = judge_differential(
+            Config::new(),
+            scenario_1(),
+            demo_impl_good(&mut stack),
+            demo_impl_zero_smart(&mut count),
+        )
+        .unwrap();
+        let d = Divergence {
+            call: 4,
+            stimulus: Pop,
+            first: vec![Value(Some(3))],
+            second: vec![Value(Some(0))],
+        };
+        assert_eq!(
+            d.to_string(),
+            "diverged at call 4 (stimulus Pop): first [Value(Some(3))], second [Value(Some(0))]"
+        );
+        assert_eq!(exit, Exit::Crashed { call: 4, error: d });
+        assert_eq!(exit.calls(), 4);
+    }
+
+    #[test]
+    fn test_arbiter() {
+        // Silence from the second object is forgiven; the judge sees the first one.
+        let mut stack = vec![];
+        let exit = judge_differential_with(
+            Config::new(),
+            scenario_1(),
+            demo_impl_good(&mut stack),
+            demo_impl_discard(),
+            |_, a, b| (b.is_empty() || a == b).then(|| a.to_vec()),
+        )
+        .unwrap();
+        assert_eq!(exit.judged().unwrap().judgment, Judgment::Done);
+
+        // The judge still judges: here, it sees the dumb object's reactions.
+        let mut stack = vec![];
+        let exit = judge_differential_with(
+            Config::new(),
+            scenario_1(),
+            demo_impl_good(&mut stack),
+            demo_impl_dumb(),
+            |_, _, b| Some(b.to_vec()),
+        )
+        .unwrap();
+        assert_eq!(
+            exit.judged().unwrap().judgment,
+            Judgment::Fault("too many reactions".to_string())
+        );
+    }
+}
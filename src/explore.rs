@@ -0,0 +1,532 @@
+//! Exhaustive, bounded model checking.
+//!
+//! A randomized judge samples the space of scenarios; for a small protocol,
+//! you can afford to cover all of it instead. An [`Explore`] judge doesn't
+//! pick its next stimulus: it lists every stimulus it could send
+//! ([`Explore::choices`]), and [`explore`] tries them all, on clones of
+//! the judge and the object, up to a bound on the number of calls.
+//!
+//! States (the judge, the object, and the reactions not yet judged) are hashed,
+//! and a state that has already been explored (at the same depth or shallower)
+//! isn't explored again. So the judge, the object, and the changes must be
+//! [`Clone`], [`Hash`], and [`Eq`]; closures won't do, implement [`Object`]
+//! on a struct instead.
+//!
+//! If any path leads to a [`Judgment::Fault`], the [`Report`] holds the
+//! shortest such path (in calls), as a [`Transcript`] you can replay.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+
+use crate::trace::{Step, Transcript};
+use crate::{Judge, Judgment, Object, StepContext};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Every stimulus a judge could send next, or the end of the simulation.
+pub type Choices<C, F> = Judgment<Vec<C>, F>;
+
+/// A judge that can list all the stimuli it could send next.
+///
+/// It's still a [`Judge`], so it can be run the usual way, too:
+/// [`pick`] implements [`Judge::next_with`] by picking one of the choices at random.
+pub trait Explore: Judge {
+    /// Judge the reactions, like [`Judge::next`], but return every stimulus
+    /// that could be sent next, rather than one of them.
+    ///
+    /// `Continue` with no choices at all means the same as `Done`.
+    ///
+    /// The judge must not commit to any of the choices yet:
+    /// the one that's taken is told with [`chose`](Explore::chose).
+    fn choices(
+        &mut self,
+        reactions: Vec<Self::Change>,
+    ) -> Result<Choices<Self::Change, Self::Fault>, Self::Error>;
+
+    /// The stimulus that was taken out of the choices.
+    ///
+    /// The default does nothing, which is right for a judge whose state
+    /// depends only on the reactions it has seen.
+    fn chose(&mut self, stimulus: &Self::Change) {
+        let _ = stimulus;
+    }
+}
+
+/// Make a move for an [`Explore`] judge: one of its choices, picked at random
+/// with the runner's generator.
+///
+/// Meant as the body of [`Judge::next_with`] (and, with a fresh
+/// [`StepContext`], of [`Judge::next`]).
+///
+/// ```
+/// use caet::explore::{pick, Choices, Explore};
+/// use caet::{judge_with, Config, Judge, Judgment, StepContext};
+/// use core::convert::Infallible;
+///
+/// /// Sends three bits, any three.
+/// struct Bits(usize);
+/// impl Judge for Bits {
+///     type Change = u8;
+///     type Fault = ();
+///     type Error = Infallible;
+///     fn next(&mut self, reactions: Vec<u8>) -> Result<Judgment<u8, ()>, Infallible> {
+///         self.next_with(reactions, &mut StepContext::new(0))
+///     }
+///     fn next_with(
+///         &mut self,
+///         reactions: Vec<u8>,
+///         cx: &mut StepContext,
+///     ) -> Result<Judgment<u8, ()>, Infallible> {
+///         pick(self, reactions, cx)
+///     }
+/// }
+/// impl Explore for Bits {
+///     fn choices(&mut self, _: Vec<u8>) -> Result<Choices<u8, ()>, Infallible> {
+///         self.0 += 1;
+///         Ok(if self.0 > 3 { Judgment::Done } else { Judgment::Continue(vec![0, 1]) })
+///     }
+/// }
+///
+/// // The runner's seed decides which bits are sent.
+/// let run = |seed| {
+///     let mut sent = vec![];
+///     judge_with(Config::new().seed(seed), Bits(0), |b| { sent.push(b); vec![] }).unwrap();
+///     sent
+/// };
+/// assert_eq!(run(7).len(), 3);
+/// assert_eq!(run(7), run(7));
+/// ```
+pub fn pick<J: Explore + ?Sized>(
+    judge: &mut J,
+    reactions: Vec<J::Change>,
+    cx: &mut StepContext,
+) -> Result<Judgment<J::Change, J::Fault>, J::Error> {
+    Ok(match judge.choices(reactions)? {
+        Judgment::Continue(mut choices) if !choices.is_empty() => {
+            let stimulus = choices.swap_remove(cx.rng().below(choices.len()));
+            judge.chose(&stimulus);
+            Judgment::Continue(stimulus)
+        }
+        Judgment::Fault(fault) => Judgment::Fault(fault),
+        _ => Judgment::Done,
+    })
+}
+
+/// In which order to explore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Order {
+    /// Shortest paths first. Stops at the first fault, which is a shortest one.
+    #[default]
+    BreadthFirst,
+    /// Longest paths first. Uses less memory for the frontier, but has to keep going
+    /// after the first fault, to make sure there isn't a shorter one.
+    DepthFirst,
+}
+
+/// How far to explore.
+///
+/// ```
+/// use caet::explore::{Order, Search};
+///
+/// let search = Search::new(8).order(Order::DepthFirst).max_states(100_000);
+/// assert_eq!(search.depth, 8);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct Search {
+    /// Maximum number of calls of the object on any path.
+    pub depth: usize,
+    /// In which order to explore.
+    pub order: Order,
+    /// Give up after this many distinct states; unlimited if `None`.
+    pub max_states: Option<usize>,
+}
+
+impl Search {
+    /// Explore every path of at most `depth` calls, breadth first.
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            order: Order::BreadthFirst,
+            max_states: None,
+        }
+    }
+
+    /// Explore in this order.
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Give up after this many distinct states.
+    pub fn max_states(mut self, states: usize) -> Self {
+        self.max_states = Some(states);
+        self
+    }
+}
+
+/// A path to a fault.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Counterexample<C, F> {
+    /// Every call on the way, in order.
+    pub path: Transcript<C>,
+    /// The fault at the end of it.
+    pub fault: F,
+}
+
+/// What [`explore`] found.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Report<C, F> {
+    /// The shortest path to a fault, if there is one within the bounds.
+    pub counterexample: Option<Counterexample<C, F>>,
+    /// Number of distinct states seen.
+    pub states: usize,
+    /// Number of calls of the object, over all paths.
+    pub calls: usize,
+    /// Whether every path was followed to its end.
+    ///
+    /// `false` if the depth bound or the state limit cut some of them short,
+    /// in which case "no counterexample" only means "none within the bounds".
+    /// Also `false` if the search left some paths once it had a counterexample
+    /// (they couldn't have led to a shorter one).
+    pub complete: bool,
+}
+
+/// A state of the universe: the judge, the object, and the reactions not yet judged.
+type State<J, O> = (J, O, Vec<<J as Judge>::Change>);
+
+/// States seen, by hash, with the shallowest depth they were seen at.
+type Seen<J, O> = BTreeMap<u64, Vec<(State<J, O>, usize)>>;
+
+/// Explore every stimulus the judge could send, to find the shortest path to a fault.
+///
+/// If the judge fails on any path, its error is returned right away.
+///
+/// See the module documentation.
+pub fn explore<J, O>(
+    search: Search,
+    judge: J,
+    object: O,
+) -> Result<Report<J::Change, J::Fault>, J::Error>
+where
+    J: Explore + Clone + Hash + Eq,
+    J::Change: Clone + Hash + Eq,
+    O: Object<J::Change> + Clone + Hash + Eq,
+{
+    // Every step taken, with the index of the step before it.
+    let mut steps: Vec<(Option<usize>, Step<J::Change>)> = Vec::new();
+    let mut seen: Seen<J, O> = BTreeMap::new();
+    let mut states = 0;
+    let mut calls = 0;
+    let mut complete = true;
+    // The fault at the end of the best path so far, and the step it ends with.
+    let mut best: Option<(J::Fault, Option<usize>, usize)> = None;
+
+    // (state, depth, index of the step that led here)
+    let mut frontier = VecDeque::new();
+    frontier.push_back(((judge, object, Vec::new()), 0, None));
+    loop {
+        let next = match search.order {
+            Order::BreadthFirst => frontier.pop_front(),
+            Order::DepthFirst => frontier.pop_back(),
+        };
+        let Some((state, depth, at)) = next else {
+            break;
+        };
+        if best.as_ref().is_some_and(|b| depth >= b.2) {
+            complete = false;
+            continue;
+        }
+
+        let bucket = seen.entry(fingerprint(&state)).or_default();
+        match bucket.iter_mut().find(|(s, _)| *s == state) {
+            Some((_, d)) if *d <= depth => continue,
+            Some((_, d)) => *d = depth,
+            None => {
+                if search.max_states.is_some_and(|max| states >= max) {
+                    complete = false;
+                    break;
+                }
+                states += 1;
+                bucket.push((state.clone(), depth));
+            }
+        }
+
+        let (mut judge, object, pending) = state;
+        let choices = match judge.choices(pending)? {
+            Judgment::Continue(choices) => choices,
+            Judgment::Done => continue,
+            Judgment::Fault(fault) => {
+                best = Some((fault, at, depth));
+                if search.order == Order::BreadthFirst {
+                    // The rest of the frontier is left unexplored.
+                    complete &= frontier.is_empty();
+                    break;
+                }
+                continue;
+            }
+        };
+        if choices.is_empty() {
+            continue;
+        }
+        if depth == search.depth {
+            complete = false;
+            continue;
+        }
+        let children = choices.into_iter().map(|stimulus| {
+            let mut judge = judge.clone();
+            let mut object = object.clone();
+            judge.chose(&stimulus);
+            let reactions = object.react(stimulus.clone());
+            steps.push((
+                at,
+                Step {
+                    stimulus,
+                    reactions: reactions.clone(),
+                    causes: Vec::new(),
+                },
+            ));
+            ((judge, object, reactions), depth + 1, Some(steps.len() - 1))
+        });
+        let children: Vec<_> = children.collect();
+        calls += children.len();
+        match search.order {
+            Order::BreadthFirst => frontier.extend(children),
+            // Reversed, so that the first choice is explored first.
+            Order::DepthFirst => frontier.extend(children.into_iter().rev()),
+        }
+    }
+
+    let counterexample = best.map(|(fault, mut at, _)| {
+        let mut path = Vec::new();
+        while let Some(i) = at {
+            let (parent, step) = &steps[i];
+            path.push(step.clone());
+            at = *parent;
+        }
+        path.reverse();
+        Counterexample {
+            path: path.into(),
+            fault,
+        }
+    });
+    Ok(Report {
+        counterexample,
+        states,
+        calls,
+        complete,
+    })
+}
+
+/// Hash a state with FNV-1a (`core` has no general-purpose hasher).
+pub(crate) fn fingerprint<T: Hash>(t: &T) -> u64 {
+    struct Fnv(u64);
+    impl Hasher for Fnv {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = (self.0 ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+    }
+    let mut h = Fnv(0xcbf2_9ce4_8422_2325);
+    t.hash(&mut h);
+    h.finish()
+}
+
+#[cfg(test)]
+mod test_explore {
+    use super::*;
+    use crate::replay::ReplayJudge;
+    use crate::test_stack::*;
+    use crate::{judge_object, Object};
+    use alloc::string::String;
+    use core::convert::Infallible;
+
+    /// Pushes 0 or 1, or pops (if there's anything to pop), with a reference stack.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+    struct Model {
+        stack: Vec<i32>,
+        expect: Option<Option<i32>>,
+    }
+
+    impl Judge for Model {
+        type Change = StackChange;
+        type Fault = String;
+        type Error = Infallible;
+        fn next(
+            &mut self,
+            reactions: Vec<StackChange>,
+        ) -> Result<Judgment<StackChange, String>, Infallible> {
+            self.next_with(reactions, &mut StepContext::new(0))
+        }
+        fn next_with(
+            &mut self,
+            reactions: Vec<StackChange>,
+            cx: &mut StepContext,
+        ) -> Result<Judgment<StackChange, String>, Infallible> {
+            pick(self, reactions, cx)
+        }
+    }
+
+    impl Explore for Model {
+        fn choices(
+            &mut self,
+            reactions: Vec<StackChange>,
+        ) -> Result<Choices<StackChange, String>, Infallible> {
+            let expected: Vec<_> = self.expect.take().map(Value).into_iter().collect();
+            if reactions != expected {
+                return Ok(Judgment::Fault(format!(
+                    "expected {expected:?}, got {reactions:?}"
+                )));
+            }
+            let mut choices = vec![Push(0), Push(1)];
+            if !self.stack.is_empty() {
+                choices.push(Pop);
+            }
+            Ok(Judgment::Continue(choices))
+        }
+        fn chose(&mut self, stimulus: &StackChange) {
+            match stimulus {
+                Push(x) => self.stack.push(*x),
+                Pop => self.expect = Some(self.stack.pop()),
+                Value(_) => unreachable!(),
+            }
+        }
+    }
+
+    /// A stack that loses everything below the top two.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+    struct Shallow(Vec<i32>);
+    impl Object<StackChange> for Shallow {
+        fn react(&mut self, msg: StackChange) -> Vec<StackChange> {
+            match msg {
+                Push(x) => {
+                    self.0.push(x);
+                    if self.0.len() > 2 {
+                        self.0.remove(0);
+                    }
+                    vec![]
+                }
+                Pop => vec![Value(self.0.pop())],
+                Value(_) => vec![],
+            }
+        }
+    }
+
+    /// A correct stack, but only as long as it holds at most two elements.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+    struct Good(Vec<i32>);
+    impl Object<StackChange> for Good {
+        fn react(&mut self, msg: StackChange) -> Vec<StackChange> {
+            match msg {
+                Push(x) => {
+                    self.0.push(x);
+                    vec![]
+                }
+                Pop => vec![Value(self.0.pop())],
+                Value(_) => vec![],
+            }
+        }
+    }
+
+    #[test]
+    fn test_shortest_counterexample() {
+        for order in [Order::BreadthFirst, Order::DepthFirst] {
+            let search = Search::new(6).order(order);
+            let r = explore(search, Model::default(), Shallow::default()).unwrap();
+            let c = r.counterexample.expect("fault");
+            // Three pushes and three pops; the third pop comes back empty.
+            assert_eq!(c.path.len(), 6, "{order:?}");
+            assert_eq!(c.path.stimuli().filter(|s| **s == Pop).count(), 3);
+            assert_eq!(c.path.steps[5].reactions, vec![Value(None)]);
+            assert!(c.fault.starts_with("expected [Value(Some("));
+            assert!(!r.complete, "{order:?}");
+
+            // It replays.
+            let o = judge_object(ReplayJudge::from(c.path), Shallow::default()).unwrap();
+            assert_eq!(o.judgment, Judgment::Done);
+        }
+    }
+
+    #[test]
+    fn test_bounds() {
+        // Too shallow to find it.
+        let r = explore(Search::new(5), Model::default(), Shallow::default()).unwrap();
+        assert_eq!(r.counterexample, None);
+        assert!(!r.complete);
+
+        // The good stack is fine, as far as we look.
+        let r = explore(Search::new(5), Model::default(), Good::default()).unwrap();
+        assert_eq!(r.counterexample, None);
+        // Every distinct stack of up to five zeros and ones, at least.
+        assert!(r.states >= 63, "{}", r.states);
+
+        let r = explore(
+            Search::new(5).max_states(10),
+            Model::default(),
+            Good::default(),
+        )
+        .unwrap();
+        assert_eq!(r.states, 10);
+        assert!(!r.complete);
+    }
+
+    #[test]
+    fn test_revisits() {
+        // Push-pop brings the universe back where it was, so hashing pays off.
+        let r = explore(Search::new(8), Model::default(), Good::default()).unwrap();
+        assert!(r.calls < 3usize.pow(8), "{}", r.calls);
+    }
+
+    /// Counts to three, then stops.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Three(u8);
+    impl Judge for Three {
+        type Change = StackChange;
+        type Fault = String;
+        type Error = Infallible;
+        fn next(
+            &mut self,
+            reactions: Vec<StackChange>,
+        ) -> Result<Judgment<StackChange, String>, Infallible> {
+            self.next_with(reactions, &mut StepContext::new(0))
+        }
+        fn next_with(
+            &mut self,
+            reactions: Vec<StackChange>,
+            cx: &mut StepContext,
+        ) -> Result<Judgment<StackChange, String>, Infallible> {
+            pick(self, reactions, cx)
+        }
+    }
+    impl Explore for Three {
+        fn choices(
+            &mut self,
+            _: Vec<StackChange>,
+        ) -> Result<Choices<StackChange, String>, Infallible> {
+            self.0 += 1;
+            Ok(if self.0 > 3 {
+                Judgment::Done
+            } else {
+                Judgment::Continue(vec![Push(self.0 as i32)])
+            })
+        }
+    }
+
+    #[test]
+    fn test_complete_and_pick() {
+        let r = explore(Search::new(10), Three(0), Good::default()).unwrap();
+        assert_eq!((r.states, r.calls, r.complete), (4, 3, true));
+        let o = judge_object(Three(0), Good::default()).unwrap();
+        assert_eq!((o.judgment, o.calls), (Judgment::Done, 3));
+    }
+}
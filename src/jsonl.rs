@@ -0,0 +1,319 @@
+//! Transcripts as [JSON Lines](https://jsonlines.org/).
+//!
+//! One JSON record per line, so a failing scenario can be checked in as a fixture
+//! and reviewed with an ordinary diff. Each line is either a step,
+//!
+//! ```text
+//! {"step":{"stimulus":{"Push":1},"reactions":[]}}
+//! ```
+//!
+//! (with the `causes` of its reactions, if the run kept track of them),
+//! or a note the judge made (see [`StepContext::annotate`](crate::StepContext::annotate)),
+//! which comes right before the step it was made at,
+//!
+//! ```text
+//! {"note":{"call":3,"text":"popping 3"}}
+//! ```
+//!
+//! or, at the very end of a [`Traced`] run, the outcome:
+//!
+//! ```text
+//! {"outcome":{"judgment":"Done","calls":7,"seed":0}}
+//! ```
+//!
+//! Blank lines are ignored when reading.
+//!
+//! Requires the `jsonl` feature.
+
+use alloc::string::String;
+use core::fmt::{self, Display, Write};
+
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+
+use crate::trace::{Note, Step, Traced, Transcript};
+use crate::{Judge, Outcome};
+
+/// A single line of a trace file.
+///
+/// `O` is the outcome type; plain transcripts don't have one.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record<C, O> {
+    Step(Step<C>),
+    Note(Note),
+    Outcome(O),
+}
+
+/// Borrowing counterpart of `Record` (for writing without cloning).
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordRef<'a, C, O> {
+    Step(&'a Step<C>),
+    Note(&'a Note),
+    Outcome(&'a O),
+}
+
+/// Why a trace couldn't be written or read.
+#[derive(Debug)]
+pub enum Error {
+    /// A line couldn't be serialized or deserialized.
+    Json {
+        /// Line number (one-based).
+        line: usize,
+        /// What `serde_json` had to say about it.
+        error: serde_json::Error,
+    },
+    /// The underlying writer failed.
+    Write(fmt::Error),
+    /// A record showed up where it doesn't belong
+    /// (an outcome in a plain transcript, or anything after the outcome).
+    Unexpected {
+        /// Line number (one-based).
+        line: usize,
+    },
+    /// A [`Traced`] run ended without an outcome record.
+    MissingOutcome,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Json { line, error } => write!(f, "line {line}: {error}"),
+            Error::Write(e) => write!(f, "write failed: {e}"),
+            Error::Unexpected { line } => write!(f, "line {line}: unexpected record"),
+            Error::MissingOutcome => write!(f, "missing outcome record"),
+        }
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(e: fmt::Error) -> Self {
+        Error::Write(e)
+    }
+}
+
+fn write_record<C, O, W>(w: &mut W, line: usize, record: &RecordRef<'_, C, O>) -> Result<(), Error>
+where
+    C: Serialize,
+    O: Serialize,
+    W: Write,
+{
+    let json = serde_json::to_string(record).map_err(|error| Error::Json { line, error })?;
+    writeln!(w, "{json}")?;
+    Ok(())
+}
+
+/// Numbered, non-blank lines of the input, parsed as records.
+fn records<C, O>(input: &str) -> impl Iterator<Item = Result<(usize, Record<C, O>), Error>> + '_
+where
+    C: DeserializeOwned,
+    O: DeserializeOwned,
+{
+    input
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(line, l)| {
+            serde_json::from_str(l)
+                .map(|r| (line, r))
+                .map_err(|error| Error::Json { line, error })
+        })
+}
+
+/// Write the steps and notes of a transcript, each note before the step it was made at.
+/// Returns the number of lines written.
+fn write_steps<C, O, W>(transcript: &Transcript<C>, w: &mut W) -> Result<usize, Error>
+where
+    C: Serialize,
+    O: Serialize,
+    W: Write,
+{
+    let mut line = 0;
+    let mut notes = transcript.notes.iter().peekable();
+    for (i, step) in transcript.steps.iter().enumerate() {
+        while let Some(note) = notes.next_if(|n| n.call <= i) {
+            line += 1;
+            write_record::<C, O, W>(w, line, &RecordRef::Note(note))?;
+        }
+        line += 1;
+        write_record::<C, O, W>(w, line, &RecordRef::Step(step))?;
+    }
+    for note in notes {
+        line += 1;
+        write_record::<C, O, W>(w, line, &RecordRef::Note(note))?;
+    }
+    Ok(line)
+}
+
+/// Write each step of the transcript as a line (and each note, if there are any).
+pub fn write_transcript<C, W>(transcript: &Transcript<C>, w: &mut W) -> Result<(), Error>
+where
+    C: Serialize,
+    W: Write,
+{
+    write_steps::<C, (), W>(transcript, w)?;
+    Ok(())
+}
+
+/// Like [`write_transcript`], but into a new string.
+pub fn transcript_to_string<C: Serialize>(transcript: &Transcript<C>) -> Result<String, Error> {
+    let mut s = String::new();
+    write_transcript(transcript, &mut s)?;
+    Ok(s)
+}
+
+/// Read a transcript written by [`write_transcript`].
+pub fn read_transcript<C>(input: &str) -> Result<Transcript<C>, Error>
+where
+    C: DeserializeOwned,
+{
+    let mut transcript = Transcript::new();
+    for record in records::<C, IgnoredAny>(input) {
+        match record? {
+            (_, Record::Step(step)) => transcript.steps.push(step),
+            (_, Record::Note(note)) => transcript.notes.push(note),
+            (line, Record::Outcome(_)) => return Err(Error::Unexpected { line }),
+        }
+    }
+    Ok(transcript)
+}
+
+/// Write the transcript of a run, followed by its outcome.
+pub fn write_traced<J, W>(traced: &Traced<J>, w: &mut W) -> Result<(), Error>
+where
+    J: Judge,
+    J::Change: Serialize,
+    J::Fault: Serialize,
+    W: Write,
+{
+    let lines = write_steps::<_, Outcome<J>, W>(&traced.transcript, w)?;
+    write_record::<J::Change, _, W>(w, lines + 1, &RecordRef::Outcome(&traced.outcome))
+}
+
+/// Read a run written by [`write_traced`].
+pub fn read_traced<J>(input: &str) -> Result<Traced<J>, Error>
+where
+    J: Judge,
+    J::Change: DeserializeOwned,
+    J::Fault: DeserializeOwned,
+{
+    let mut transcript = Transcript::new();
+    let mut outcome = None;
+    for record in records::<J::Change, Outcome<J>>(input) {
+        let (line, record) = record?;
+        if outcome.is_some() {
+            return Err(Error::Unexpected { line });
+        }
+        match record {
+            Record::Step(step) => transcript.steps.push(step),
+            Record::Note(note) => transcript.notes.push(note),
+            Record::Outcome(o) => outcome = Some(o),
+        }
+    }
+    match outcome {
+        Some(outcome) => Ok(Traced {
+            outcome,
+            transcript,
+        }),
+        None => Err(Error::MissingOutcome),
+    }
+}
+
+#[cfg(test)]
+mod test_jsonl {
+    use super::*;
+    use crate::causal::{judge_causal, Immediate};
+    use crate::test_stack::*;
+    use crate::trace::judge_traced;
+    use crate::Judgment;
+
+    #[test]
+    fn test_roundtrip_traced() {
+        let t = judge_traced(scenario_1(), demo_impl_dumb()).unwrap();
+        let mut s = String::new();
+        write_traced(&t, &mut s).unwrap();
+        assert_eq!(
+            s,
+            concat!(
+                "{\"step\":{\"stimulus\":{\"Push\":1},\"reactions\":[{\"Value\":null}]}}\n",
+                "{\"outcome\":{\"judgment\":{\"Fault\":\"too many reactions\"},\"calls\":1,\"seed\":0}}\n",
+            )
+        );
+        let u: Traced<StackJudge> = read_traced(&s).unwrap();
+        assert_eq!(u.transcript, t.transcript);
+        assert_eq!(u.outcome.judgment, t.outcome.judgment);
+        assert_eq!(u.outcome.calls, t.outcome.calls);
+    }
+
+    #[test]
+    fn test_roundtrip_transcript() {
+        let mut stack = vec![];
+        let t = judge_traced(scenario_1(), demo_impl_good(&mut stack)).unwrap();
+        assert_eq!(t.outcome.judgment, Judgment::Done);
+        let s = transcript_to_string(&t.transcript).unwrap();
+        assert_eq!(s.lines().count(), 7);
+        // Blank lines are fine.
+        let s = s.replace('\n', "\n\n");
+        assert_eq!(read_transcript(&s).unwrap(), t.transcript);
+    }
+
+    #[test]
+    fn test_roundtrip_causes() {
+        let mut stack = vec![];
+        let config = crate::Config::new();
+        let t = judge_causal(config, scenario_1(), Immediate(demo_impl_good(&mut stack))).unwrap();
+        let s = transcript_to_string(&t.transcript).unwrap();
+        assert_eq!(
+            s.lines().nth(3),
+            Some(r#"{"step":{"stimulus":"Pop","reactions":[{"Value":3}],"causes":[[3]]}}"#)
+        );
+        assert_eq!(read_transcript(&s).unwrap(), t.transcript);
+    }
+
+    #[test]
+    fn test_roundtrip_notes() {
+        let mut t = Transcript::new();
+        t.push(Push(1), vec![]);
+        t.push(Pop, vec![Value(Some(1))]);
+        let note = |call, text: &str| Note {
+            call,
+            text: text.to_string(),
+        };
+        t.notes = vec![note(0, "start"), note(1, "pop"), note(2, "end")];
+        let s = transcript_to_string(&t).unwrap();
+        let kinds: Vec<_> = s.lines().map(|l| &l[2..6]).collect();
+        assert_eq!(kinds, ["note", "step", "note", "step", "note"]);
+        assert_eq!(read_transcript(&s).unwrap(), t);
+    }
+
+    #[test]
+    fn test_read_seedless() {
+        // Written before outcomes had a seed (or a time).
+        let old = "{\"step\":{\"stimulus\":{\"Push\":1},\"reactions\":[]}}\n\
+                   {\"outcome\":{\"judgment\":\"Done\",\"calls\":1}}\n";
+        let t = read_traced::<StackJudge>(old).unwrap();
+        assert_eq!((t.outcome.judgment, t.outcome.calls), (Judgment::Done, 1));
+        assert_eq!((t.outcome.seed, t.outcome.time), (0, 0));
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let bad = "{\"step\":{\"stimulus\":\"Pop\",\"reactions\":[]}}\n{\"nope\":1}\n";
+        assert!(matches!(
+            read_transcript::<StackChange>(bad),
+            Err(Error::Json { line: 2, .. })
+        ));
+        let early = "{\"outcome\":{\"judgment\":\"Done\",\"calls\":0,\"seed\":0}}\n\
+                     {\"step\":{\"stimulus\":\"Pop\",\"reactions\":[]}}\n";
+        assert!(matches!(
+            read_traced::<StackJudge>(early),
+            Err(Error::Unexpected { line: 2 })
+        ));
+        assert!(matches!(
+            read_traced::<StackJudge>(""),
+            Err(Error::MissingOutcome)
+        ));
+    }
+}
@@ -24,7 +24,7 @@
 //! - Prototyping: You can use this crate to prototype your design.
 //! - Validation: You can use this crate to validate your design.
 //! - Mocking: You can use this crate to mock your design. However,
-//! you'll also be mocking your implementation, which may or may not be what you want.
+//!   you'll also be mocking your implementation, which may or may not be what you want.
 //!
 //! ### Benefits Profile
 //!
@@ -65,23 +65,26 @@
 //!
 //! Now, let's get to the details.
 //!
-//! This crate has exactly three public types:
+//! At its core, this crate has three public types:
 //! - trait [`Judge`]: A god-like entity that controls the universe and judges the object's reactions.
 //! - enum [`Judgment`]: A judgment about an object's reaction evaluated at a particular time.
-//! Also contains the next input to the object.
+//!   Also contains the next input to the object.
 //! - struct [`Outcome`]: The final result of a test.
 //!
 //! Your job is to implement the trait [`Judge`].
 //!
 //! And, essentially, one public function:
 //! - [`judge`]: The entry point into this crate. This is a test runner.
-//! It takes the ownership of your judge implementation and your object,
-//! and runs the simulation. Once it's finished, it returns an [`Outcome`].
+//!   It takes the ownership of your judge implementation and your object,
+//!   and runs the simulation. Once it's finished, it returns an [`Outcome`].
 //!
 //! So, it's actually pretty simple. You implement the trait [`Judge`],
 //! you implement your object, then you test it by calling [`judge`].
 //! That's it.
 //!
+//! (If you'd rather watch the simulation unfold one step at a time,
+//! use a [`Simulation`] instead. [`judge`] is just a shortcut for running one to the end.)
+//!
 //! ### First, a synopsis.
 //!
 //! The `caet` crate is a flexible testing system.
@@ -129,11 +132,11 @@
 //! The judge is also responsible for judging the object's reactions.
 //! Both of these responsibilities are combined in the return value of [`Judge::next`]:
 //! - If the judge decides the reaction is acceptable and still has a challenge for the object,
-//! it will return `Ok(Judgment::Continue(next_input))` with whatever the `next_input` is.
+//!   it will return `Ok(Judgment::Continue(next_input))` with whatever the `next_input` is.
 //! - If the judge decides the reaction is acceptable but has no more challenges for the object,
-//! it will return `Ok(Judgment::Done)` to end the simulation.
+//!   it will return `Ok(Judgment::Done)` to end the simulation.
 //! - Lastly, if the judge decides the reaction is unacceptable, it will return
-//! `Err(Judgment::Fault(fault))` with whatever the `fault` is. This also ends the simulation.
+//!   `Err(Judgment::Fault(fault))` with whatever the `fault` is. This also ends the simulation.
 //!
 //! In [`Judge::next`], you'll be implementing the judge's decision-making process.
 //!
@@ -155,17 +158,30 @@
 //! Let's break this down.
 //!
 //! - `FnMut`: The object has private state that it can mutate, which it persists
-//! while it is called multiple times.
+//!   while it is called multiple times.
 //! - `(J::Change)`: It makes a passive observation of the universe, though it is
-//! actually given by the judge.
+//!   actually given by the judge.
 //! - `-> Vec<J::Change>`: It reacts to the observation by producing a vector of changes.
-//! It's a vector because it can produce multiple changes at once, or none at all.
-//! Pay close attention to the doctrine of non-immediacy of reactions: This doctrine
-//! says that, unlike observations, reactions are not immediate. In other words,
-//! it's possible for the object to bunch up its reactions and produce them all at once
-//! as a way to defeat the judge. The judge should generally agree that this is a valid strategy.
-//! Otherwise, the judge is at fault. (But of course, this is up to how you implement your judge.
-//! I only strongly recommend that you follow this doctrine.)
+//!   It's a vector because it can produce multiple changes at once, or none at all.
+//!   Pay close attention to the doctrine of non-immediacy of reactions: This doctrine
+//!   says that, unlike observations, reactions are not immediate. In other words,
+//!   it's possible for the object to bunch up its reactions and produce them all at once
+//!   as a way to defeat the judge. The judge should generally agree that this is a valid strategy.
+//!   Otherwise, the judge is at fault. (But of course, this is up to how you implement your judge.
+//!   I only strongly recommend that you follow this doctrine.)
+//!
+//! [`judge`] takes ownership of the judge and the object. If you'd like to look at them
+//! after the simulation (say, to compare the judge's reference model with the object's state),
+//! use [`judge_returning`] or [`judge_borrowed`] instead.
+//!
+//! If your object can crash, let the closure return a `Result` and run it with
+//! [`judge_fallible`], which reports crashes as [`Exit::Crashed`].
+//! Or, with the `std` feature, let it panic and run it with `judge_catch_unwind`,
+//! which reports panics (in the object or the judge) as [`Exit::Panicked`].
+//!
+//! If you'd like the object to have a name, to be reset, or to describe its state
+//! in fault messages, implement the trait [`Object`] (which every such closure
+//! already implements) and run it with [`judge_object`] instead.
 //!
 //! So in summary, the "object" is abstracted away, hidden behind the `FnMut` closure.
 //! The `caet` crate will never ever touch it directly. Instead, you will be providing
@@ -188,6 +204,8 @@
 //!
 //! (But none of the examples given respect them fully. But, they are just examples, anyway.)
 //!
+//! To see whether your judge respects them, wrap your object in a [`wire::Wire`].
+//!
 //! ### [`judge`] (lowercase)
 //!
 //! The function [`judge`] takes in exactly two arguments:
@@ -208,6 +226,59 @@
 //! type has a iteration count field. Check that field to see
 //! if it's way too low.
 //!
+//! Conversely, a buggy judge may never stop at all. To keep that from hanging your tests,
+//! run the simulation with [`judge_with`] and a [`Config`] that limits the number of calls.
+//!
+//! A judge that wants randomness (to pick stimuli adversarially, say) should take it from
+//! the runner, through the [`StepContext`] given to [`Judge::next_with`]. The runner seeds it
+//! from [`Config::seed`] and records the seed in the [`Outcome`], so any run can be repeated.
+//! The same context also tells the judge how many calls have been made so far
+//! (so it needn't count them itself), keeps a logical clock, and takes annotations
+//! for the [`trace`].
+//!
+//! ### Beyond [`judge`]
+//!
+//! The three types and the [`judge`] function are all you need.
+//! The modules below are optional helpers built on top of them.
+//!
+//! - [`trace`]: Run the simulation while recording a transcript of every
+//!   stimulus and reaction, so you can see what led to a fault.
+//! - [`causal`]: Tag every stimulus with an ID, let the object say which stimuli caused
+//!   its reactions, and measure how long each request waited for an answer.
+//! - [`replay`]: Replay a recorded transcript against a patched object,
+//!   and find out where its reactions diverge from the recording.
+//! - `jsonl` (feature `jsonl`): Save transcripts as JSON Lines, one step per line,
+//!   and load them back. The `serde` feature alone derives `Serialize` and `Deserialize`
+//!   for the data types.
+//! - [`differential`]: Run an old and a new object side by side, on the same stimuli,
+//!   and find the first call where they disagree.
+//! - [`shrink`]: Shrink a long failing stimulus sequence down to a minimal one
+//!   that still faults (optionally, with the same kind of fault).
+//! - [`explore`]: Try every stimulus a judge could send, up to a bound, and find
+//!   the shortest path to a fault (for small protocols, certainty beats sampling).
+//! - [`matcher`]: Match reactions against exact values, wildcards, and predicates,
+//!   and capture the values the object makes up, for later stimuli and expectations.
+//! - [`script`]: Write a judge as a script of stimuli to send and reactions to expect,
+//!   with the [`scenario!`] macro.
+//! - [`model`]: Judge an object against a reference model, with as much lag
+//!   and batching as the doctrines (or your design) allow.
+//! - [`linearize`]: Check that the invocations and returns of a concurrent object
+//!   can be explained by a sequential specification (linearizability).
+//! - [`time`]: Run the simulation on a virtual clock, so judges can schedule stimuli
+//!   for later and objects can set timers (for timeouts, retries, and rate limits).
+//! - [`des`]: The event queue under the clock, with cancellation and rescheduling,
+//!   and arrival processes (scheduled, periodic, Poisson, bursty) to fill it with.
+//! - [`deadline`]: Register the responses a judge is owed, each due within so many calls
+//!   (or so much virtual time), and get a fault naming the stimulus when one is late.
+//! - [`nested`]: Let an object run inner simulations, each with its own judge and object,
+//!   and collect their outcomes in a tree (divide and conquer).
+//! - [`wire`]: Put the object behind an unreliable wire that delays, drops, duplicates,
+//!   and re-orders its reactions, to check that the judge honors the doctrines below.
+//! - `property` (feature `proptest`): Generate scenarios with `proptest` strategies,
+//!   count faults as property failures, and let `proptest` shrink the failing scenario.
+//! - [`rng`]: A small seedable random number generator, for reproducible randomness.
+//! - [`context`]: What the runner lends the judge on every turn.
+//!
 //! ## Some doctrines that may help
 //!
 //! Let's first refine the concepts of observations and reactions.
@@ -217,27 +288,27 @@
 //! ### Observations
 //!
 //! - **Passive**: The object senses things passively. It can't
-//! choose to sense something or not, neither can it arrange
-//! some signal to arrive at a certain time. In `caet`, this effect
-//! is achieved by letting the judge control all sensations (observations)
-//! felt by the object.
+//!   choose to sense something or not, neither can it arrange
+//!   some signal to arrive at a certain time. In `caet`, this effect
+//!   is achieved by letting the judge control all sensations (observations)
+//!   felt by the object.
 //! - **Immediate**: The object's observations are immediate. They are
-//! always up-to-date. This also means they always arrive in order.
-//! In fact, a "delayed sensation" (or observation) is a contradiction
-//! in terms; like a triangle with four corners, it cannot even be imagined.
+//!   always up-to-date. This also means they always arrive in order.
+//!   In fact, a "delayed sensation" (or observation) is a contradiction
+//!   in terms; like a triangle with four corners, it cannot even be imagined.
 //! - **Reliable**: Similarly, all observations "felt" by the object are reliable.
-//! Again, this is by definition of the very term "observation."
-//! Implementation-wise, this means `caet` cannot drop or mutate observations sent by the judge.
+//!   Again, this is by definition of the very term "observation."
+//!   Implementation-wise, this means `caet` cannot drop or mutate observations sent by the judge.
 //!
 //! ### Reactions
 //!
 //! - **Active**: The object has total and unimpeachable agency over its reactions.
-//! Of course, some actions can be disallowed by the judge, but in any case,
-//! the object will always be looking out for its own interests.
+//!   Of course, some actions can be disallowed by the judge, but in any case,
+//!   the object will always be looking out for its own interests.
 //! - **Non-immediate**: In the real world, reactions are not immediate.
-//! They can also be re-ordered.
+//!   They can also be re-ordered.
 //! - **Non-reliable**: Similarly, reactions are not reliable. They can be dropped
-//! or mutated.
+//!   or mutated.
 //!
 //! However, `caet` doesn't enforce the non-immediacy and non-reliability doctrines,
 //! meaning, a judge implementation may actually demand immediate and reliable reactions.
@@ -422,17 +493,53 @@
 
 #![deny(missing_docs)]
 #![cfg_attr(not(test), no_std)]
+// For `test_long_scenario`, in `script`.
+#![cfg_attr(test, recursion_limit = "256")]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
-use alloc::vec;
+use alloc::string::String;
 use alloc::vec::Vec;
 
+pub mod causal;
+pub mod context;
+pub mod deadline;
+pub mod des;
+pub mod differential;
+pub mod explore;
+#[cfg(feature = "jsonl")]
+pub mod jsonl;
+pub mod linearize;
+pub mod matcher;
+pub mod model;
+pub mod nested;
+pub mod object;
+#[cfg(feature = "proptest")]
+pub mod property;
+pub mod replay;
+pub mod rng;
+pub mod script;
+pub mod shrink;
+pub mod simulation;
+pub mod time;
+pub mod trace;
+pub mod wire;
+
+pub use context::StepContext;
+pub use object::Object;
+pub use simulation::Simulation;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 /// A judgment of a cause-effect system.
 ///
 /// - Did the subject produce an acceptable reaction?
 /// - And, if so, should the judge continue or halt the program?
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Judgment<M, S> {
     /// Acceptable; continue with this input.
     Continue(M),
@@ -473,22 +580,87 @@ pub trait Judge {
         &mut self,
         reactions: Vec<Self::Change>,
     ) -> Result<Judgment<Self::Change, Self::Fault>, Self::Error>;
+
+    /// Like [`Judge::next`], but with the runner's [`StepContext`],
+    /// which provides, among other things, a seeded random number generator.
+    ///
+    /// The runners in this crate always call this method, never [`Judge::next`] directly.
+    /// By default, it ignores the context and calls [`Judge::next`], so a judge
+    /// that doesn't need the context doesn't have to know about it.
+    ///
+    /// A judge that does need it overrides this method, and implements
+    /// [`Judge::next`] for callers that have no context to give
+    /// (for instance, with `self.next_with(reactions, &mut StepContext::new(0))`).
+    fn next_with(
+        &mut self,
+        reactions: Vec<Self::Change>,
+        cx: &mut StepContext,
+    ) -> Result<Judgment<Self::Change, Self::Fault>, Self::Error> {
+        let _ = cx;
+        self.next(reactions)
+    }
+}
+
+/// A borrowed judge is still a judge.
+impl<J: Judge + ?Sized> Judge for &mut J {
+    type Change = J::Change;
+    type Fault = J::Fault;
+    type Error = J::Error;
+    fn next(
+        &mut self,
+        reactions: Vec<Self::Change>,
+    ) -> Result<Judgment<Self::Change, Self::Fault>, Self::Error> {
+        (**self).next(reactions)
+    }
+    fn next_with(
+        &mut self,
+        reactions: Vec<Self::Change>,
+        cx: &mut StepContext,
+    ) -> Result<Judgment<Self::Change, Self::Fault>, Self::Error> {
+        (**self).next_with(reactions, cx)
+    }
 }
 
 /// The final judgment of a cause-effect system.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(bound(
+        serialize = "J::Change: Serialize, J::Fault: Serialize",
+        deserialize = "J::Change: Deserialize<'de>, J::Fault: Deserialize<'de>"
+    ))
+)]
 pub struct Outcome<J: Judge> {
     /// The final judgment.
     ///
     /// See also: [`judge`].
     ///
     /// - The [`Done`](Judgment::Done) judgment is the only one that
-    /// should be returned in a functioning system.
+    ///   should be returned in a functioning system.
     /// - [`Continue`](Judgment::Continue) and [`Fault`](Judgment::Fault) judgments
-    /// indicate errors in the judge and the subject, respectively.
+    ///   indicate errors in the judge and the subject, respectively.
     pub judgment: Judgment<J::Change, J::Fault>,
     /// Number of times the judge has called the task.
     pub calls: usize,
+    /// The seed of the runner's random number generator (see [`StepContext::rng`]).
+    ///
+    /// Run the simulation again with [`Config::seed`] set to this,
+    /// and a judge that only uses that generator will do exactly the same thing.
+    ///
+    /// (Traces recorded before there was a seed were recorded with zero.)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seed: u64,
+    /// The virtual time when the simulation ended (see [`time`]);
+    /// always zero for runners without a clock.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_zero"))]
+    pub time: time::Time,
+}
+
+/// Whether it's time zero (which goes without saying in serialized outcomes).
+#[cfg(feature = "serde")]
+fn is_zero(time: &time::Time) -> bool {
+    *time == 0
 }
 
 /// I got too lazy to convert the old code that didn't have the [`Outcome`] type
@@ -503,6 +675,273 @@ impl<J: Judge> PrivateOutcomeDecompose<J> for Outcome<J> {
     }
 }
 
+/// How a simulation ended, for runners where more can go wrong
+/// than the judge can judge.
+///
+/// [`judge`] only ever ends in an [`Outcome`] (or a judge error), so it returns that directly.
+/// The other runners may also stop because of the object itself, so they return this instead.
+///
+/// - [`Judged`](Exit::Judged): The judge ended the simulation. See [`Outcome`].
+/// - [`Crashed`](Exit::Crashed): The object reported that it crashed.
+///   See [`judge_fallible`] (and [`differential::judge_differential`],
+///   for which two objects reacting differently is a crash).
+/// - [`Panicked`](Exit::Panicked): The judge or the object panicked.
+///   See `judge_catch_unwind` (feature `std`).
+/// - [`Exhausted`](Exit::Exhausted): The simulation ran out of budget.
+///   See [`judge_with`].
+#[non_exhaustive]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(bound(
+        serialize = "J::Change: Serialize, J::Fault: Serialize, E: Serialize",
+        deserialize = "J::Change: Deserialize<'de>, J::Fault: Deserialize<'de>, E: Deserialize<'de>"
+    ))
+)]
+pub enum Exit<J: Judge, E = core::convert::Infallible> {
+    /// The judge ended the simulation.
+    Judged(Outcome<J>),
+    /// The object crashed.
+    Crashed {
+        /// The call that crashed (one-based, so it's also the number of calls made).
+        call: usize,
+        /// The object's explanation.
+        error: E,
+    },
+    /// The judge or the object panicked.
+    Panicked(Panic<J::Change>),
+    /// A limit in the [`Config`] was hit before the judge ended the simulation.
+    Exhausted {
+        /// Which limit was hit.
+        limit: Limit,
+        /// Number of calls made.
+        calls: usize,
+        /// Number of reactions produced.
+        reactions: usize,
+        /// The virtual time when it was hit (see [`time`]);
+        /// always zero for runners without a clock.
+        #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_zero"))]
+        time: time::Time,
+    },
+}
+
+/// How a simulation may be run.
+///
+/// ```
+/// use caet::Config;
+///
+/// let config = Config::new().max_calls(10_000).max_reactions(1_000_000);
+/// assert_eq!(config.calls, Some(10_000));
+/// ```
+///
+/// See also: [`judge_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct Config {
+    /// Maximum number of calls of the object; unlimited if `None`.
+    pub calls: Option<usize>,
+    /// Maximum number of reactions the object may produce in total; unlimited if `None`.
+    pub reactions: Option<usize>,
+    /// Seed for the runner's random number generator (see [`StepContext::rng`]).
+    pub seed: u64,
+    /// Latest virtual time to run until, for runners with a clock; unlimited if `None`.
+    pub time: Option<time::Time>,
+}
+
+impl Config {
+    /// No limits. Same as [`judge`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call the object at most this many times.
+    pub fn max_calls(mut self, calls: usize) -> Self {
+        self.calls = Some(calls);
+        self
+    }
+
+    /// Let the object produce at most this many reactions, in total.
+    pub fn max_reactions(mut self, reactions: usize) -> Self {
+        self.reactions = Some(reactions);
+        self
+    }
+
+    /// Stop the clock once it would pass this time (see [`time`]).
+    pub fn max_time(mut self, time: time::Time) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Seed the runner's random number generator with this (the default is zero).
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// A limit in a [`Config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Limit {
+    /// [`Config::calls`].
+    Calls,
+    /// [`Config::reactions`].
+    Reactions,
+    /// [`Config::time`].
+    Time,
+}
+
+/// A party to the simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Party {
+    /// The [`Judge`].
+    Judge,
+    /// The object.
+    Object,
+}
+
+/// A panic caught in the middle of a simulation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Panic<C> {
+    /// Who panicked.
+    pub who: Party,
+    /// Number of calls of the object, including the one that panicked, if it was the object.
+    pub calls: usize,
+    /// The panic message, if it was a string.
+    pub message: Option<String>,
+    /// The last stimulus delivered to the object, if any.
+    pub stimulus: Option<C>,
+}
+
+impl<J: Judge, E> Exit<J, E> {
+    /// The outcome, if the judge ended the simulation.
+    pub fn judged(self) -> Option<Outcome<J>> {
+        match self {
+            Exit::Judged(o) => Some(o),
+            _ => None,
+        }
+    }
+
+    /// Same, by reference.
+    pub fn outcome(&self) -> Option<&Outcome<J>> {
+        match self {
+            Exit::Judged(o) => Some(o),
+            _ => None,
+        }
+    }
+
+    /// Number of times the object was called, including any call that crashed.
+    pub fn calls(&self) -> usize {
+        match self {
+            Exit::Judged(o) => o.calls,
+            Exit::Crashed { call, .. } => *call,
+            Exit::Panicked(p) => p.calls,
+            Exit::Exhausted { calls, .. } => *calls,
+        }
+    }
+}
+
+// (`derive` can't see through `Outcome<J>` to bound `J::Fault`, so spell these out.)
+impl<J: Judge, E: core::fmt::Debug> core::fmt::Debug for Exit<J, E>
+where
+    Outcome<J>: core::fmt::Debug,
+    J::Change: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Exit::Judged(o) => f.debug_tuple("Judged").field(o).finish(),
+            Exit::Crashed { call, error } => f
+                .debug_struct("Crashed")
+                .field("call", call)
+                .field("error", error)
+                .finish(),
+            Exit::Panicked(p) => f.debug_tuple("Panicked").field(p).finish(),
+            Exit::Exhausted {
+                limit,
+                calls,
+                reactions,
+                time,
+            } => f
+                .debug_struct("Exhausted")
+                .field("limit", limit)
+                .field("calls", calls)
+                .field("reactions", reactions)
+                .field("time", time)
+                .finish(),
+        }
+    }
+}
+impl<J: Judge, E: Clone> Clone for Exit<J, E>
+where
+    Outcome<J>: Clone,
+    J::Change: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Exit::Judged(o) => Exit::Judged(o.clone()),
+            Exit::Crashed { call, error } => Exit::Crashed {
+                call: *call,
+                error: error.clone(),
+            },
+            Exit::Panicked(p) => Exit::Panicked(p.clone()),
+            Exit::Exhausted {
+                limit,
+                calls,
+                reactions,
+                time,
+            } => Exit::Exhausted {
+                limit: *limit,
+                calls: *calls,
+                reactions: *reactions,
+                time: *time,
+            },
+        }
+    }
+}
+impl<J: Judge, E: PartialEq> PartialEq for Exit<J, E>
+where
+    Outcome<J>: PartialEq,
+    J::Change: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Exit::Judged(a), Exit::Judged(b)) => a == b,
+            (
+                Exit::Crashed { call, error },
+                Exit::Crashed {
+                    call: call2,
+                    error: error2,
+                },
+            ) => call == call2 && error == error2,
+            (Exit::Panicked(a), Exit::Panicked(b)) => a == b,
+            (
+                Exit::Exhausted {
+                    limit,
+                    calls,
+                    reactions,
+                    time,
+                },
+                Exit::Exhausted {
+                    limit: limit2,
+                    calls: calls2,
+                    reactions: reactions2,
+                    time: time2,
+                },
+            ) => limit == limit2 && calls == calls2 && reactions == reactions2 && time == time2,
+            _ => false,
+        }
+    }
+}
+impl<J: Judge, E: Eq> Eq for Exit<J, E>
+where
+    Outcome<J>: Eq,
+    J::Change: Eq,
+{
+}
+
 /// A test driver for a cause-effect system.
 ///
 /// Test drive the task with the judge, and return the number
@@ -510,33 +949,188 @@ impl<J: Judge> PrivateOutcomeDecompose<J> for Outcome<J> {
 ///
 /// See the `test_stack` module in the source code for an example.
 ///
-/// See also: [`judge_panic`], [`Judge`], [`Outcome`].
+/// See also: [`judge_panic`], [`judge_object`], [`Judge`], [`Outcome`].
 pub fn judge<J>(
-    mut judge: J,
-    mut object: impl FnMut(J::Change) -> Vec<J::Change>,
+    judge: J,
+    object: impl FnMut(J::Change) -> Vec<J::Change>,
 ) -> Result<Outcome<J>, J::Error>
 where
     J: Judge,
 {
-    use core::mem;
-    let mut out = vec![];
-    let mut ite = 0;
+    judge_object(judge, object)
+}
+
+/// Like [`judge`], but the object can be anything that implements [`Object`],
+/// not just a closure.
+pub fn judge_object<J, O>(judge: J, object: O) -> Result<Outcome<J>, J::Error>
+where
+    J: Judge,
+    O: Object<J::Change>,
+{
+    let mut sim = Simulation::new(judge, object);
+    sim.run_to_end()?;
+    Ok(unlimited(sim.into_exit()))
+}
+
+/// Without limits, the judge is the only one who can end a simulation.
+fn unlimited<J: Judge>(exit: Option<Exit<J>>) -> Outcome<J> {
+    match exit {
+        Some(Exit::Judged(o)) => o,
+        _ => unreachable!("simulation without limits ended without a judgment"),
+    }
+}
+
+/// The result of a simulation, along with the judge and the object.
+///
+/// See [`judge_returning`].
+pub struct PostMortem<J: Judge, O> {
+    /// What [`judge`] would have returned.
+    pub result: Result<Outcome<J>, J::Error>,
+    /// The judge, as it was at the end.
+    pub judge: J,
+    /// The object, as it was at the end.
+    pub object: O,
+}
+
+/// Like [`judge_object`], but hand the judge and the object back afterwards,
+/// so you can inspect them (e.g., the judge's reference model or the object's state).
+///
+/// See also: [`judge_borrowed`].
+pub fn judge_returning<J, O>(judge: J, object: O) -> PostMortem<J, O>
+where
+    J: Judge,
+    O: Object<J::Change>,
+{
+    let mut sim = Simulation::new(judge, object);
+    let result = sim.run_to_end().map(|_| ());
+    let (judge, object, exit) = sim.into_parts();
+    PostMortem {
+        result: result.map(|()| unlimited(exit)),
+        judge,
+        object,
+    }
+}
+
+/// Like [`judge_object`], but only borrow the judge and the object,
+/// so you can inspect them afterwards.
+///
+/// See also: [`judge_returning`].
+pub fn judge_borrowed<J, O>(judge: &mut J, object: &mut O) -> Result<Outcome<J>, J::Error>
+where
+    J: Judge,
+    O: Object<J::Change> + ?Sized,
+{
+    let o = judge_object(judge, object::Borrowed(object))?;
+    Ok(Outcome {
+        judgment: o.judgment,
+        calls: o.calls,
+        seed: o.seed,
+        time: o.time,
+    })
+}
+
+/// Like [`judge`], but within the limits of a [`Config`], and with its seed.
+///
+/// A buggy judge that never says [`Done`](Judgment::Done) would make [`judge`]
+/// loop forever. Here, the simulation stops with [`Exit::Exhausted`] instead:
+/// - before the object would be called more than [`Config::calls`] times, or
+/// - as soon as the object has produced more than [`Config::reactions`] reactions in total.
+pub fn judge_with<J>(
+    config: Config,
+    judge: J,
+    object: impl FnMut(J::Change) -> Vec<J::Change>,
+) -> Result<Exit<J>, J::Error>
+where
+    J: Judge,
+{
+    let mut sim = Simulation::with_config(config, judge, object);
+    sim.run_to_end()?;
+    Ok(sim.into_exit().expect("over"))
+}
+
+/// Like [`judge_with`], but the object may crash.
+///
+/// Real systems crash. Instead of panicking (which tears down the whole test),
+/// the reaction function can return an error, which ends the simulation with
+/// [`Exit::Crashed`]. That way, you can tell apart:
+/// - a subject fault ([`Judgment::Fault`] in [`Exit::Judged`]),
+/// - an object crash ([`Exit::Crashed`]), and
+/// - a judge error (`Err`).
+pub fn judge_fallible<J, E>(
+    config: Config,
+    judge: J,
+    object: impl FnMut(J::Change) -> Result<Vec<J::Change>, E>,
+) -> Result<Exit<J, E>, J::Error>
+where
+    J: Judge,
+{
+    let mut sim = Simulation::build(config, judge, object);
+    while sim.advance_by(|_| (), |object, msg| object(msg))?.is_some() {}
+    Ok(sim.into_exit().expect("over"))
+}
+
+/// Like [`judge_with`], but catch panics in the judge and the object.
+///
+/// Normally, a panic inside [`Judge::next`] or the reaction function unwinds
+/// straight through [`judge`], and all you get is the panic message.
+/// Here, the panic is caught and turned into [`Exit::Panicked`], which
+/// says who panicked, after how many calls, and what the last stimulus was.
+///
+/// (The panic hook still runs, so the message is still printed as usual.)
+///
+/// Requires the `std` feature.
+#[cfg(feature = "std")]
+pub fn judge_catch_unwind<J>(
+    config: Config,
+    judge: J,
+    object: impl FnMut(J::Change) -> Vec<J::Change>,
+) -> Result<Exit<J>, J::Error>
+where
+    J: Judge,
+    J::Change: Clone,
+{
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // Neither party is touched again after a panic, so unwind safety is moot.
+    let mut sim = Simulation::with_config(config, judge, object);
+    let mut last = None;
     loop {
-        let jud = judge.next(mem::take(&mut out));
-        match jud {
-            Ok(Judgment::Continue(msg)) => {
-                out = object(msg);
-                ite += 1;
+        // Set once the judge has made its move, so a panic after that is the object's.
+        let mut sent = None;
+        match catch_unwind(AssertUnwindSafe(|| {
+            sim.advance(|msg| sent = Some(msg.clone()))
+        })) {
+            Ok(stepped) => {
+                if stepped?.is_none() {
+                    break;
+                }
+                last = sent;
             }
-            Ok(j) => {
-                return Ok(Outcome {
-                    judgment: j,
-                    calls: ite,
-                })
+            Err(payload) => {
+                let (who, calls, stimulus) = match sent {
+                    Some(msg) => (Party::Object, sim.calls() + 1, Some(msg)),
+                    None => (Party::Judge, sim.calls(), last),
+                };
+                sim.end(Exit::Panicked(Panic {
+                    who,
+                    calls,
+                    message: panic_message(payload),
+                    stimulus,
+                }));
+                break;
             }
-            Err(e) => return Err(e),
         }
     }
+    Ok(sim.into_exit().expect("over"))
+}
+
+/// The message of a `panic!`, which is almost always a string.
+#[cfg(feature = "std")]
+fn panic_message(payload: alloc::boxed::Box<dyn core::any::Any + Send>) -> Option<String> {
+    match payload.downcast::<String>() {
+        Ok(s) => Some(*s),
+        Err(payload) => payload.downcast_ref::<&str>().map(|s| String::from(*s)),
+    }
 }
 
 /// Like [`judge`], but panic on any error, either due to the judge
@@ -547,10 +1141,29 @@ where
     J::Error: core::fmt::Display,
     J::Fault: core::fmt::Display,
 {
-    match judge(j, object).map(|o| o.decompose()) {
+    judge_object_panic(j, object)
+}
+
+/// Like [`judge_object`], but panic on any error, either due to the judge
+/// or the object.
+///
+/// The panic message includes the object's [name](Object::name)
+/// and [state](Object::describe_state), if it has them.
+pub fn judge_object_panic<J, O>(j: J, object: O) -> usize
+where
+    J: Judge,
+    J::Error: core::fmt::Display,
+    J::Fault: core::fmt::Display,
+    O: Object<J::Change>,
+{
+    let mut sim = Simulation::new(j, object);
+    let result = sim.run_to_end().map(|_| ());
+    let obj = object::describe(sim.object());
+    let result = result.map(|()| unlimited(sim.into_exit()).decompose());
+    match result {
         Ok((Judgment::Done, count)) => count,
         Ok((Judgment::Fault(why), count)) => {
-            panic!("subject fault (iter count: {count}): {why}")
+            panic!("subject fault (iter count: {count}){obj}: {why}")
         }
         Ok((Judgment::Continue(_), count)) => {
             panic!("judge fault (iter count: {count}): judge stopped at continue")
@@ -568,13 +1181,14 @@ mod test_stack {
 
     use super::*;
 
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-    enum StackChange {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub(crate) enum StackChange {
         Push(i32),
         Pop,
         Value(Option<i32>),
     }
-    use StackChange::*;
+    pub(crate) use StackChange::*;
 
     /// An interactive judge for a stack with buffering.
     ///
@@ -592,14 +1206,14 @@ mod test_stack {
     /// At least, it can properly judge implementations that do
     /// produce some reactions.
     #[derive(Default, Debug, Clone, PartialEq, Eq)]
-    struct StackJudge {
+    pub(crate) struct StackJudge {
         /// List of pushes and pops to simulate.
-        scenario: VecDeque<StackChange>,
+        pub(crate) scenario: VecDeque<StackChange>,
         /// Reference implementation of the stack.
-        ref_impl: Vec<i32>,
+        pub(crate) ref_impl: Vec<i32>,
         /// The reactions that must be produced by the subject
         /// arranged in order.
-        expect: VecDeque<i32>,
+        pub(crate) expect: VecDeque<i32>,
     }
     impl Judge for StackJudge {
         type Error = String;
@@ -660,7 +1274,7 @@ mod test_stack {
                 }
                 // Let it go, here, and wait for the subject to produce the same reaction
                 // (*eventually*---the subject is allowed to delay reporting any pops).
-                return Ok(Judgment::Continue(act));
+                Ok(Judgment::Continue(act))
             } else {
                 // The scenario is exhausted.
 
@@ -689,7 +1303,7 @@ mod test_stack {
             Self::default()
         }
         /// Real constructor right here.
-        fn new_scenario(scenario: Vec<StackChange>) -> Self {
+        pub(crate) fn new_scenario(scenario: Vec<StackChange>) -> Self {
             Self {
                 scenario: scenario.into(),
                 ..Default::default()
@@ -698,7 +1312,7 @@ mod test_stack {
     }
 
     /// A valid push-and-pop scenario.
-    fn scenario_1() -> StackJudge {
+    pub(crate) fn scenario_1() -> StackJudge {
         #[rustfmt::skip]
         let sce = vec![
             Push(1), Push(2), Push(3),
@@ -709,7 +1323,9 @@ mod test_stack {
         StackJudge::new_scenario(sce)
     }
 
-    fn demo_impl_good(stack: &mut Vec<i32>) -> impl FnMut(StackChange) -> Vec<StackChange> + '_ {
+    pub(crate) fn demo_impl_good(
+        stack: &mut Vec<i32>,
+    ) -> impl FnMut(StackChange) -> Vec<StackChange> + '_ {
         move |msg| {
             let ret = match msg {
                 Push(x) => {
@@ -740,7 +1356,7 @@ mod test_stack {
     ///
     /// (This is because the judge allows buffering implementations, but doesn't
     /// have a force the subject to flush its buffer.)
-    fn demo_impl_discard() -> impl FnMut(StackChange) -> Vec<StackChange> {
+    pub(crate) fn demo_impl_discard() -> impl FnMut(StackChange) -> Vec<StackChange> {
         move |_| vec![]
     }
 
@@ -753,7 +1369,7 @@ mod test_stack {
     }
 
     /// This implementation will produce a value even if the command is 'push.'
-    fn demo_impl_dumb() -> impl FnMut(StackChange) -> Vec<StackChange> {
+    pub(crate) fn demo_impl_dumb() -> impl FnMut(StackChange) -> Vec<StackChange> {
         move |msg| match msg {
             Push(_) => vec![Value(None)],
             Pop => vec![Value(Some(0))],
@@ -770,7 +1386,9 @@ mod test_stack {
     }
 
     /// This implementation will remember the stack size, but will report '0' for everything.
-    fn demo_impl_zero_smart(count: &mut usize) -> impl FnMut(StackChange) -> Vec<StackChange> + '_ {
+    pub(crate) fn demo_impl_zero_smart(
+        count: &mut usize,
+    ) -> impl FnMut(StackChange) -> Vec<StackChange> + '_ {
         move |msg| match msg {
             Push(_) => {
                 *count += 1;
@@ -809,7 +1427,7 @@ mod test_stack {
     /// This implementation will always report an empty stack.
     ///
     /// Like `demo_impl_discard`, this is a "valid" implementation.
-    fn demo_impl_empty() -> impl FnMut(StackChange) -> Vec<StackChange> {
+    pub(crate) fn demo_impl_empty() -> impl FnMut(StackChange) -> Vec<StackChange> {
         move |msg: StackChange| match msg {
             Push(_) => vec![],
             Pop => vec![Value(None)],
@@ -826,7 +1444,7 @@ mod test_stack {
     }
 
     /// This implementation will return something irrelevant when popping
-    fn demo_impl_irrelevant() -> impl FnMut(StackChange) -> Vec<StackChange> {
+    pub(crate) fn demo_impl_irrelevant() -> impl FnMut(StackChange) -> Vec<StackChange> {
         move |msg: StackChange| match msg {
             Push(_) => vec![],
             Pop => vec![Push(42)],
@@ -847,7 +1465,7 @@ mod test_stack {
 
     /// This scenario contains an implementation bug
     /// (too many pops).
-    fn scenario_2() -> StackJudge {
+    pub(crate) fn scenario_2() -> StackJudge {
         #[rustfmt::skip]
         let sce = vec![
             Push(1), Pop, Pop
@@ -864,7 +1482,7 @@ mod test_stack {
 
     /// This scenario is when there's items in the stack after the last
     /// operation. This is a valid scenario.
-    fn scenario_3() -> StackJudge {
+    pub(crate) fn scenario_3() -> StackJudge {
         #[rustfmt::skip]
         let sce = vec![
             Push(1), Push(2), Push(3),
@@ -883,7 +1501,7 @@ mod test_stack {
     }
 
     /// This scenario tests a subject that only pops when the stack is empty.
-    fn scenario_4() -> StackJudge {
+    pub(crate) fn scenario_4() -> StackJudge {
         #[rustfmt::skip]
         let sce = vec![
             Push(1), Push(2), Push(3),
@@ -895,7 +1513,7 @@ mod test_stack {
     }
 
     /// And a corresponding subject implementation.
-    fn demo_impl_lazy() -> impl FnMut(StackChange) -> Vec<StackChange> {
+    pub(crate) fn demo_impl_lazy() -> impl FnMut(StackChange) -> Vec<StackChange> {
         let mut stack = vec![];
         let mut count = 0;
         move |msg: StackChange| {
@@ -937,3 +1555,272 @@ mod test_stack {
         assert_eq!(j, Judgment::Done);
     }
 }
+
+#[cfg(test)]
+mod test_fallible {
+    //! Objects that can crash.
+
+    use super::test_stack::*;
+    use super::*;
+
+    /// A stack that runs out of room after two items.
+    fn demo_impl_small() -> impl FnMut(StackChange) -> Result<Vec<StackChange>, &'static str> {
+        let mut stack = vec![];
+        move |msg| match msg {
+            Push(_) if stack.len() == 2 => Err("stack overflow"),
+            Push(x) => {
+                stack.push(x);
+                Ok(vec![])
+            }
+            Pop => Ok(vec![Value(stack.pop())]),
+            Value(_) => panic!("Value in demo_impl"),
+        }
+    }
+
+    #[test]
+    fn test_crash() {
+        let exit = judge_fallible(Config::new(), scenario_1(), demo_impl_small()).unwrap();
+        assert_eq!(
+            exit,
+            Exit::Crashed {
+                call: 3,
+                error: "stack overflow"
+            }
+        );
+        assert_eq!(exit.calls(), 3);
+    }
+
+    #[test]
+    fn test_no_crash() {
+        let sce = vec![Push(1), Push(2), Pop, Push(3), Pop, Pop];
+        let exit = judge_fallible(
+            Config::new(),
+            StackJudge::new_scenario(sce),
+            demo_impl_small(),
+        )
+        .unwrap();
+        let o = exit.judged().unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+        assert_eq!(o.calls, 6);
+    }
+
+    #[test]
+    fn test_judge_error() {
+        let exit = judge_fallible(Config::new(), scenario_2(), demo_impl_small());
+        assert_eq!(exit, Err("bad sim: more pops than pushes".to_string()));
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test_catch_unwind {
+    //! Panics in the judge or the object.
+
+    use super::test_stack::*;
+    use super::*;
+
+    #[test]
+    fn test_object_panic() {
+        let exit = judge_catch_unwind(Config::new(), scenario_1(), |msg| match msg {
+            Pop => panic!("pop {}", 42),
+            _ => vec![],
+        })
+        .unwrap();
+        assert_eq!(
+            exit,
+            Exit::Panicked(Panic {
+                who: Party::Object,
+                calls: 4,
+                message: Some("pop 42".to_string()),
+                stimulus: Some(Pop),
+            })
+        );
+    }
+
+    #[test]
+    fn test_judge_panic() {
+        #[derive(Debug, PartialEq)]
+        struct Grumpy(usize);
+        impl Judge for Grumpy {
+            type Change = StackChange;
+            type Fault = String;
+            type Error = String;
+            fn next(
+                &mut self,
+                _: Vec<StackChange>,
+            ) -> Result<Judgment<StackChange, String>, String> {
+                self.0 += 1;
+                if self.0 > 2 {
+                    panic!("enough");
+                }
+                Ok(Judgment::Continue(Push(self.0 as i32)))
+            }
+        }
+        let exit = judge_catch_unwind(Config::new(), Grumpy(0), demo_impl_discard()).unwrap();
+        assert_eq!(
+            exit,
+            Exit::Panicked(Panic {
+                who: Party::Judge,
+                calls: 2,
+                message: Some("enough".to_string()),
+                stimulus: Some(Push(2)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_no_panic() {
+        let exit = judge_catch_unwind(Config::new(), scenario_4(), demo_impl_lazy()).unwrap();
+        assert_eq!(exit.judged().unwrap().judgment, Judgment::Done);
+    }
+}
+
+#[cfg(test)]
+mod test_budget {
+    //! Judges that never stop.
+
+    use super::test_stack::*;
+    use super::*;
+
+    /// Pushes forever.
+    #[derive(Debug, PartialEq)]
+    struct Endless;
+    impl Judge for Endless {
+        type Change = StackChange;
+        type Fault = String;
+        type Error = String;
+        fn next(&mut self, _: Vec<StackChange>) -> Result<Judgment<StackChange, String>, String> {
+            Ok(Judgment::Continue(Push(0)))
+        }
+    }
+
+    #[test]
+    fn test_max_calls() {
+        let exit = judge_with(Config::new().max_calls(100), Endless, demo_impl_dumb()).unwrap();
+        assert_eq!(
+            exit,
+            Exit::Exhausted {
+                limit: Limit::Calls,
+                calls: 100,
+                reactions: 100,
+                time: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_reactions() {
+        let config = Config::new().max_calls(100).max_reactions(10);
+        let exit = judge_with(config, Endless, demo_impl_dumb()).unwrap();
+        assert_eq!(
+            exit,
+            Exit::Exhausted {
+                limit: Limit::Reactions,
+                calls: 11,
+                reactions: 11,
+                time: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_within_budget() {
+        // Exactly 7 calls and 3 reactions.
+        let config = Config::new().max_calls(7).max_reactions(3);
+        let mut stack = vec![];
+        let exit = judge_with(config, scenario_1(), demo_impl_good(&mut stack)).unwrap();
+        assert_eq!(exit.judged().unwrap().judgment, Judgment::Done);
+    }
+
+    #[test]
+    fn test_fallible_budget() {
+        let config = Config::new().max_calls(50).seed(9);
+        let mut dumb = demo_impl_dumb();
+        let exit = judge_fallible(config, Endless, |msg| Ok::<_, ()>(dumb(msg))).unwrap();
+        assert_eq!(exit.calls(), 50);
+        assert!(matches!(
+            exit,
+            Exit::Exhausted {
+                limit: Limit::Calls,
+                ..
+            }
+        ));
+
+        // The seed makes it into the outcome.
+        let mut stack = vec![];
+        let mut good = demo_impl_good(&mut stack);
+        let exit = judge_fallible(config, scenario_1(), |msg| Ok::<_, ()>(good(msg))).unwrap();
+        assert_eq!(exit.judged().unwrap().seed, 9);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_catch_unwind_budget() {
+        let config = Config::new().max_reactions(10);
+        let exit = judge_catch_unwind(config, Endless, demo_impl_dumb()).unwrap();
+        assert_eq!(
+            exit,
+            Exit::Exhausted {
+                limit: Limit::Reactions,
+                calls: 11,
+                reactions: 11,
+                time: 0,
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_post_mortem {
+    //! Looking at the judge and the object after the simulation.
+
+    use super::test_stack::*;
+    use super::*;
+
+    /// A stack whose contents can be inspected.
+    #[derive(Default)]
+    struct Stack(Vec<i32>);
+    impl Object<StackChange> for Stack {
+        fn react(&mut self, msg: StackChange) -> Vec<StackChange> {
+            match msg {
+                Push(x) => {
+                    self.0.push(x);
+                    vec![]
+                }
+                Pop => vec![Value(self.0.pop())],
+                Value(_) => panic!("Value in demo_impl"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_returning() {
+        let pm = judge_returning(scenario_3(), Stack::default());
+        assert_eq!(pm.result.unwrap().judgment, Judgment::Done);
+        assert_eq!(pm.judge.ref_impl, vec![1, 2]);
+        assert_eq!(pm.object.0, pm.judge.ref_impl);
+    }
+
+    #[test]
+    fn test_returning_error() {
+        let pm = judge_returning(scenario_2(), Stack::default());
+        assert_eq!(pm.result, Err("bad sim: more pops than pushes".to_string()));
+        assert!(pm.judge.scenario.is_empty());
+    }
+
+    #[test]
+    fn test_borrowed() {
+        let mut j = scenario_1();
+        let mut stack = Stack::default();
+        let o = judge_borrowed(&mut j, &mut stack).unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+        assert_eq!(j.ref_impl, vec![1]);
+        assert_eq!(stack.0, vec![1]);
+
+        // Closures work too, and can be used again.
+        let mut lazy = demo_impl_lazy();
+        let o = judge_borrowed(&mut scenario_4(), &mut lazy).unwrap();
+        assert_eq!(o.calls, 10);
+        let o = judge_borrowed(&mut scenario_4(), &mut lazy).unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+    }
+}
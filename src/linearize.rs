@@ -0,0 +1,637 @@
+//! Checking concurrent histories for linearizability.
+//!
+//! A concurrent object (a lock-free queue, a replicated register) is called by
+//! several clients at once, so its operations overlap in time: each one is
+//! *invoked*, and, some time later, *returns*. Such an object is correct
+//! (*linearizable*) if every operation seems to take effect at a single instant
+//! between its invocation and its return, in an order that a plain sequential
+//! [`Spec`] agrees with.
+//!
+//! In a simulation, invocations are usually stimuli and returns are reactions,
+//! possibly delayed (see the doctrine of the non-immediacy of reactions).
+//! Record them in a [`History`], and [`check`] it against the specification.
+//! Or, wrap the judge that drives the simulation in [`Linearizability`],
+//! which does both and turns a non-linearizable history into a [`Judgment::Fault`].
+//!
+//! The search is Wing and Gong's: repeatedly pick an operation that could have
+//! taken effect first, apply it to the specification, and backtrack if the
+//! result doesn't match. States already known to be dead ends are remembered
+//! (as in Lowe's refinement), so the specification must be [`Hash`] and [`Eq`].
+//!
+//! ```
+//! use caet::linearize::{check, History, Spec};
+//!
+//! /// A register holding a number.
+//! #[derive(Clone, Hash, PartialEq, Eq)]
+//! struct Register(i32);
+//! impl Spec for Register {
+//!     type Op = Option<i32>; // Write, or read
+//!     type Ret = i32;
+//!     fn apply(&mut self, op: &Option<i32>) -> i32 {
+//!         if let Some(x) = op {
+//!             self.0 = *x;
+//!         }
+//!         self.0
+//!     }
+//! }
+//!
+//! // A write of 1 overlaps a read that sees 0: fine, the read went first.
+//! let mut h = History::new();
+//! h.call(1, Some(1));
+//! h.call(2, None);
+//! h.ret(2, 0);
+//! h.ret(1, 1);
+//! assert!(check(Register(0), &h).is_ok());
+//!
+//! // But if the write had returned before the read began, it wouldn't be.
+//! let mut h = History::new();
+//! h.call(1, Some(1));
+//! h.ret(1, 1);
+//! h.call(2, None);
+//! h.ret(2, 0);
+//! assert!(check(Register(0), &h).is_err());
+//! ```
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display};
+use core::hash::Hash;
+
+use crate::explore::fingerprint;
+use crate::{Judge, Judgment, StepContext};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A sequential specification: what the object should do, one operation at a time.
+pub trait Spec: Clone {
+    /// An operation, with its arguments.
+    type Op;
+    /// What an operation returns.
+    type Ret: PartialEq;
+
+    /// Perform the operation, and say what it returns.
+    fn apply(&mut self, op: &Self::Op) -> Self::Ret;
+}
+
+/// A change, seen as part of a concurrent history.
+///
+/// Operations are matched by `id`: a return belongs to the latest invocation
+/// with the same `id` that hasn't returned yet. A client number will do.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Event<Op, Ret> {
+    /// An operation was invoked.
+    Call {
+        /// Which operation.
+        id: usize,
+        /// The operation.
+        op: Op,
+    },
+    /// An operation returned.
+    Return {
+        /// Which operation.
+        id: usize,
+        /// What it returned.
+        ret: Ret,
+    },
+}
+
+/// An operation in a [`History`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Operation<Op, Ret> {
+    /// The `id` it was invoked with.
+    pub id: usize,
+    /// The operation.
+    pub op: Op,
+    /// What it returned, or `None` if it hasn't (yet).
+    pub ret: Option<Ret>,
+    /// When it was invoked (the index of the event in the history).
+    pub called: usize,
+    /// When it returned, if it has.
+    pub returned: Option<usize>,
+}
+
+/// Invocations and returns, in the order they happened.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct History<Op, Ret> {
+    ops: Vec<Operation<Op, Ret>>,
+    events: usize,
+}
+
+impl<Op, Ret> Default for History<Op, Ret> {
+    fn default() -> Self {
+        Self {
+            ops: Vec::new(),
+            events: 0,
+        }
+    }
+}
+
+impl<Op, Ret> History<Op, Ret> {
+    /// An empty history.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an invocation.
+    pub fn call(&mut self, id: usize, op: Op) {
+        self.ops.push(Operation {
+            id,
+            op,
+            ret: None,
+            called: self.events,
+            returned: None,
+        });
+        self.events += 1;
+    }
+
+    /// Record a return.
+    ///
+    /// Returns `false` (and records nothing) if no operation with this `id` is pending.
+    pub fn ret(&mut self, id: usize, ret: Ret) -> bool {
+        let Some(op) = self
+            .ops
+            .iter_mut()
+            .rev()
+            .find(|o| o.id == id && o.returned.is_none())
+        else {
+            return false;
+        };
+        op.ret = Some(ret);
+        op.returned = Some(self.events);
+        self.events += 1;
+        true
+    }
+
+    /// Record an event; see [`call`](History::call) and [`ret`](History::ret).
+    pub fn record(&mut self, event: Event<Op, Ret>) -> bool {
+        match event {
+            Event::Call { id, op } => {
+                self.call(id, op);
+                true
+            }
+            Event::Return { id, ret } => self.ret(id, ret),
+        }
+    }
+
+    /// The operations, in the order they were invoked.
+    pub fn operations(&self) -> &[Operation<Op, Ret>] {
+        &self.ops
+    }
+}
+
+/// Why a history isn't linearizable.
+///
+/// No order of the operations works; the one that got furthest is shown,
+/// along with the operations that could have come next, but didn't fit.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Counterexample<Op, Ret> {
+    /// The whole history.
+    pub history: History<Op, Ret>,
+    /// The longest valid order found, as indices into [`History::operations`].
+    pub longest: Vec<usize>,
+    /// The operations that could have come after it (also indices),
+    /// none of which returned what the specification says.
+    pub stuck: Vec<usize>,
+}
+
+impl<Op: Debug, Ret: Debug> Display for Counterexample<Op, Ret> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ops = self.history.operations();
+        let show = |f: &mut fmt::Formatter<'_>, i: usize| {
+            let o = &ops[i];
+            match &o.ret {
+                Some(ret) => write!(f, "#{} {:?} -> {:?}", o.id, o.op, ret),
+                None => write!(f, "#{} {:?} (pending)", o.id, o.op),
+            }
+        };
+        write!(f, "not linearizable: after [")?;
+        for (n, &i) in self.longest.iter().enumerate() {
+            if n > 0 {
+                write!(f, ", ")?;
+            }
+            show(f, i)?;
+        }
+        write!(f, "], none of [")?;
+        for (n, &i) in self.stuck.iter().enumerate() {
+            if n > 0 {
+                write!(f, ", ")?;
+            }
+            show(f, i)?;
+        }
+        write!(f, "] fits")
+    }
+}
+
+/// Decide whether the history is linearizable with respect to the specification,
+/// which starts out as `spec`.
+///
+/// If it is, returns an order that works (as indices into [`History::operations`]).
+/// Pending operations (invoked, but never returned) may or may not have taken effect;
+/// those that are in the order did.
+pub fn check<S>(
+    spec: S,
+    history: &History<S::Op, S::Ret>,
+) -> Result<Vec<usize>, Counterexample<S::Op, S::Ret>>
+where
+    S: Spec + Hash + Eq,
+    S::Op: Clone,
+    S::Ret: Clone,
+{
+    let mut search = Search {
+        ops: history.operations(),
+        dead: BTreeMap::new(),
+        order: Vec::new(),
+        longest: Vec::new(),
+        stuck: Vec::new(),
+    };
+    let done = vec![0u64; history.ops.len().div_ceil(64)];
+    if search.run(spec, done) {
+        Ok(search.order)
+    } else {
+        Err(Counterexample {
+            history: history.clone(),
+            longest: search.longest,
+            stuck: search.stuck,
+        })
+    }
+}
+
+/// The state of a Wing–Gong search.
+struct Search<'a, S: Spec> {
+    ops: &'a [Operation<S::Op, S::Ret>],
+    /// (Operations done, specification) pairs known to be dead ends, by hash.
+    dead: BTreeMap<u64, Vec<(Vec<u64>, S)>>,
+    /// The order so far.
+    order: Vec<usize>,
+    /// The longest order found so far, and where it got stuck.
+    longest: Vec<usize>,
+    stuck: Vec<usize>,
+}
+
+impl<S> Search<'_, S>
+where
+    S: Spec + Hash + Eq,
+{
+    fn run(&mut self, spec: S, done: Vec<u64>) -> bool {
+        let left: Vec<_> = (0..self.ops.len())
+            .filter(|&i| done[i / 64] & (1 << (i % 64)) == 0)
+            .collect();
+        // Every operation that returned has to take effect before the first return.
+        let Some(first) = left.iter().filter_map(|&i| self.ops[i].returned).min() else {
+            return true;
+        };
+        let key = (done, spec);
+        let bucket = self.dead.entry(fingerprint(&key)).or_default();
+        if bucket.contains(&key) {
+            return false;
+        }
+        let (done, spec) = key;
+
+        let candidates: Vec<_> = left
+            .into_iter()
+            .filter(|&i| self.ops[i].called < first)
+            .collect();
+        for &i in &candidates {
+            let mut next = spec.clone();
+            let ret = next.apply(&self.ops[i].op);
+            if self.ops[i].ret.as_ref().is_some_and(|r| *r != ret) {
+                continue;
+            }
+            let mut done = done.clone();
+            done[i / 64] |= 1 << (i % 64);
+            self.order.push(i);
+            if self.run(next, done) {
+                return true;
+            }
+            self.order.pop();
+        }
+
+        if self.order.len() >= self.longest.len() {
+            self.longest.clone_from(&self.order);
+            self.stuck = candidates;
+        }
+        let key = (done, spec);
+        self.dead.entry(fingerprint(&key)).or_default().push(key);
+        false
+    }
+}
+
+/// Why a [`Linearizability`] judge faulted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Violation<F, Op, Ret> {
+    /// The wrapped judge faulted.
+    Judge(F),
+    /// A return didn't match any pending invocation.
+    Unmatched {
+        /// Its `id`.
+        id: usize,
+        /// Number of calls of the object so far.
+        call: usize,
+    },
+    /// The history isn't linearizable.
+    NotLinearizable(Counterexample<Op, Ret>),
+}
+
+impl<F: Display, Op: Debug, Ret: Debug> Display for Violation<F, Op, Ret> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Judge(fault) => Display::fmt(fault, f),
+            Violation::Unmatched { id, call } => {
+                write!(f, "return without invocation: #{id} (call {call})")
+            }
+            Violation::NotLinearizable(c) => Display::fmt(c, f),
+        }
+    }
+}
+
+/// A judge that records the history of a simulation and, when the wrapped judge
+/// is done, checks that it is linearizable.
+///
+/// Every stimulus and every reaction is passed to `classify`,
+/// which says which ones are invocations and returns (and which are neither).
+/// The wrapped judge decides which stimuli to send, and when to stop, as usual.
+pub struct Linearizability<J: Judge, S: Spec, K> {
+    judge: J,
+    spec: S,
+    classify: K,
+    history: History<S::Op, S::Ret>,
+}
+
+impl<J, S, K> Linearizability<J, S, K>
+where
+    J: Judge,
+    S: Spec,
+    K: FnMut(&J::Change) -> Option<Event<S::Op, S::Ret>>,
+{
+    /// Wrap the judge; check the history against `spec` (in its initial state).
+    pub fn new(judge: J, spec: S, classify: K) -> Self {
+        Self {
+            judge,
+            spec,
+            classify,
+            history: History::new(),
+        }
+    }
+
+    /// The history so far.
+    pub fn history(&self) -> &History<S::Op, S::Ret> {
+        &self.history
+    }
+
+    /// The wrapped judge.
+    pub fn into_inner(self) -> J {
+        self.judge
+    }
+
+    fn record(
+        &mut self,
+        change: &J::Change,
+        call: usize,
+    ) -> Option<Violation<J::Fault, S::Op, S::Ret>> {
+        let event = (self.classify)(change)?;
+        let id = match &event {
+            Event::Call { id, .. } | Event::Return { id, .. } => *id,
+        };
+        if self.history.record(event) {
+            None
+        } else {
+            Some(Violation::Unmatched { id, call })
+        }
+    }
+}
+
+impl<J, S, K> Judge for Linearizability<J, S, K>
+where
+    J: Judge,
+    S: Spec + Hash + Eq,
+    S::Op: Clone,
+    S::Ret: Clone,
+    K: FnMut(&J::Change) -> Option<Event<S::Op, S::Ret>>,
+{
+    type Change = J::Change;
+    type Fault = Violation<J::Fault, S::Op, S::Ret>;
+    type Error = J::Error;
+
+    fn next(
+        &mut self,
+        reactions: Vec<Self::Change>,
+    ) -> Result<Judgment<Self::Change, Self::Fault>, Self::Error> {
+        self.next_with(reactions, &mut StepContext::new(0))
+    }
+
+    fn next_with(
+        &mut self,
+        reactions: Vec<Self::Change>,
+        cx: &mut StepContext,
+    ) -> Result<Judgment<Self::Change, Self::Fault>, Self::Error> {
+        for r in &reactions {
+            if let Some(v) = self.record(r, cx.call()) {
+                return Ok(Judgment::Fault(v));
+            }
+        }
+        Ok(match self.judge.next_with(reactions, cx)? {
+            Judgment::Continue(stimulus) => match self.record(&stimulus, cx.call()) {
+                Some(v) => Judgment::Fault(v),
+                None => Judgment::Continue(stimulus),
+            },
+            Judgment::Done => match check(self.spec.clone(), &self.history) {
+                Ok(_) => Judgment::Done,
+                Err(c) => Judgment::Fault(Violation::NotLinearizable(c)),
+            },
+            Judgment::Fault(f) => Judgment::Fault(Violation::Judge(f)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_linearize {
+    use super::*;
+    use crate::{judge, Object};
+    use alloc::format;
+    use core::convert::Infallible;
+
+    /// A FIFO queue.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+    struct Queue(Vec<i32>);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum QueueOp {
+        Enq(i32),
+        Deq,
+    }
+    use QueueOp::*;
+
+    impl Spec for Queue {
+        type Op = QueueOp;
+        type Ret = Option<i32>;
+        fn apply(&mut self, op: &QueueOp) -> Option<i32> {
+            match op {
+                Enq(x) => {
+                    self.0.push(*x);
+                    None
+                }
+                Deq => (!self.0.is_empty()).then(|| self.0.remove(0)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_overlapping() {
+        // Two enqueues overlap, so either order is fine...
+        let mut h = History::new();
+        h.call(1, Enq(1));
+        h.call(2, Enq(2));
+        h.ret(2, None);
+        h.ret(1, None);
+        h.call(1, Deq);
+        h.ret(1, Some(2));
+        h.call(1, Deq);
+        h.ret(1, Some(1));
+        assert_eq!(check(Queue::default(), &h), Ok(vec![1, 0, 2, 3]));
+
+        // ...but not if they don't.
+        let mut h = History::new();
+        h.call(1, Enq(1));
+        h.ret(1, None);
+        h.call(2, Enq(2));
+        h.ret(2, None);
+        h.call(1, Deq);
+        h.ret(1, Some(2));
+        let c = check(Queue::default(), &h).unwrap_err();
+        assert_eq!(c.longest, [0, 1]);
+        assert_eq!(c.stuck, [2]);
+        assert_eq!(
+            format!("{c}"),
+            "not linearizable: after [#1 Enq(1) -> None, #2 Enq(2) -> None], \
+             none of [#1 Deq -> Some(2)] fits"
+        );
+    }
+
+    #[test]
+    fn test_pending() {
+        // A pending enqueue may have taken effect...
+        let mut h = History::new();
+        h.call(1, Enq(1));
+        h.call(2, Deq);
+        h.ret(2, Some(1));
+        assert_eq!(check(Queue::default(), &h), Ok(vec![0, 1]));
+        // ...or not.
+        let mut h = History::new();
+        h.call(1, Enq(1));
+        h.call(2, Deq);
+        h.ret(2, None);
+        assert_eq!(check(Queue::default(), &h), Ok(vec![1]));
+        assert!(!h.ret(3, None));
+    }
+
+    #[test]
+    fn test_many() {
+        // Lots of overlapping enqueues of the same value: without the memo,
+        // every one of their 12! orders would be tried; with it, only their 2^12 subsets.
+        let mut h = History::new();
+        for id in 0..12 {
+            h.call(id, Enq(7));
+        }
+        for id in 0..12 {
+            h.ret(id, None);
+        }
+        h.call(0, Deq);
+        h.ret(0, Some(8));
+        assert!(check(Queue::default(), &h).is_err());
+    }
+
+    /// Invocations go in, returns come out.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Msg {
+        Call(usize, QueueOp),
+        Ret(usize, Option<i32>),
+    }
+
+    fn classify(m: &Msg) -> Option<Event<QueueOp, Option<i32>>> {
+        Some(match *m {
+            Msg::Call(id, op) => Event::Call { id, op },
+            Msg::Ret(id, ret) => Event::Return { id, ret },
+        })
+    }
+
+    /// A queue that answers each call at the next one, and dequeues from the wrong end.
+    #[derive(Default)]
+    struct Sloppy {
+        items: Vec<i32>,
+        late: Option<Msg>,
+    }
+    impl Object<Msg> for Sloppy {
+        fn react(&mut self, msg: Msg) -> Vec<Msg> {
+            let Msg::Call(id, op) = msg else {
+                return vec![];
+            };
+            let ret = match op {
+                Enq(x) => {
+                    self.items.push(x);
+                    None
+                }
+                Deq => self.items.pop(),
+            };
+            self.late.replace(Msg::Ret(id, ret)).into_iter().collect()
+        }
+    }
+
+    fn run(stimuli: Vec<Msg>) -> Judgment<Msg, Violation<Infallible, QueueOp, Option<i32>>> {
+        let judge_ = Linearizability::new(Script(stimuli), Queue::default(), classify);
+        let mut sloppy = Sloppy::default();
+        judge(judge_, move |m| sloppy.react(m)).unwrap().judgment
+    }
+
+    /// Sends the stimuli, whatever the reactions.
+    struct Script(Vec<Msg>);
+    impl Judge for Script {
+        type Change = Msg;
+        type Fault = Infallible;
+        type Error = Infallible;
+        fn next(&mut self, _: Vec<Msg>) -> Result<Judgment<Msg, Self::Fault>, Self::Error> {
+            Ok(if self.0.is_empty() {
+                Judgment::Done
+            } else {
+                Judgment::Continue(self.0.remove(0))
+            })
+        }
+    }
+
+    #[test]
+    fn test_judge() {
+        // The two enqueues overlap, so the dequeue may well get the second one.
+        let ok = run(vec![
+            Msg::Call(1, Enq(1)),
+            Msg::Call(2, Enq(2)),
+            Msg::Call(1, Deq),
+            Msg::Call(2, Enq(3)),
+        ]);
+        assert_eq!(ok, Judgment::Done);
+        // But not the third one.
+        let bad = run(vec![
+            Msg::Call(1, Enq(1)),
+            Msg::Call(2, Enq(2)),
+            Msg::Call(3, Enq(3)),
+            Msg::Call(1, Deq),
+            Msg::Call(2, Deq),
+            Msg::Call(3, Deq),
+        ]);
+        assert!(
+            matches!(bad, Judgment::Fault(Violation::NotLinearizable(_))),
+            "{bad:?}"
+        );
+        // Answering a call that wasn't made is caught right away.
+        let stray = run(vec![Msg::Ret(9, None)]);
+        assert_eq!(
+            stray,
+            Judgment::Fault(Violation::Unmatched { id: 9, call: 0 })
+        );
+    }
+}
@@ -0,0 +1,509 @@
+//! Matching reactions against expectations.
+//!
+//! A judge usually compares the reactions it gets with the ones it expects,
+//! by hand, with `match` arms. A [`Matcher`] says what's expected of one reaction:
+//! exactly this ([`exact`](Matcher::exact)), anything ([`any`](Matcher::any)),
+//! anything for which a predicate holds ([`predicate`](Matcher::predicate)),
+//! or one of several things ([`any_of`](Matcher::any_of)).
+//! [`match_batch`] checks a whole batch of reactions, in order or in any order,
+//! and explains the first mismatch with an [`Unmatched`], ready to be a fault.
+//!
+//! Objects often make up values the judge can't know in advance,
+//! like the ID of a new session. A matcher can [`capture`](Matcher::capture) such a value
+//! into [`Captures`], under a name; later stimuli and matchers
+//! (see [`using`](Matcher::using)) can then refer to it.
+//!
+//! ```
+//! use caet::matcher::{match_batch, Captures, Matcher};
+//! use caet::model::Batching;
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Change {
+//!     Open,
+//!     Opened(u32),
+//!     Close(u32),
+//!     Closed(u32),
+//! }
+//! use Change::*;
+//!
+//! let mut caps = Captures::new();
+//! let opened = Matcher::capture("id", |c| match c {
+//!     Opened(id) => Some(*id),
+//!     _ => None,
+//! });
+//! match_batch(&[opened], &[Opened(7)], Batching::InOrder, &mut caps).unwrap();
+//! assert_eq!(caps["id"], 7);
+//!
+//! // Close that session, and expect it to be closed.
+//! assert_eq!(Close(caps["id"]), Close(7));
+//! let closed = Matcher::using(|caps| Matcher::exact(Closed(caps["id"])));
+//! let err = match_batch(&[closed], &[Closed(8)], Batching::InOrder, &mut caps).unwrap_err();
+//! assert_eq!(err.to_string(), "expected Closed(7), got Closed(8) (reaction 0)");
+//! ```
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display};
+use core::ops::Index;
+
+use crate::model::Batching;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Values captured from reactions, by name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Captures<V> {
+    values: BTreeMap<String, V>,
+}
+
+impl<V> Default for Captures<V> {
+    fn default() -> Self {
+        Self {
+            values: BTreeMap::new(),
+        }
+    }
+}
+
+impl<V> Captures<V> {
+    /// Nothing captured yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value captured under this name, if any.
+    pub fn get(&self, name: &str) -> Option<&V> {
+        self.values.get(name)
+    }
+
+    /// Capture a value by hand (or overwrite one).
+    pub fn insert(&mut self, name: impl Into<String>, value: V) {
+        self.values.insert(name.into(), value);
+    }
+}
+
+/// Panics if nothing was captured under this name.
+impl<V> Index<&str> for Captures<V> {
+    type Output = V;
+    fn index(&self, name: &str) -> &V {
+        match self.get(name) {
+            Some(v) => v,
+            None => panic!("nothing captured as {name:?}"),
+        }
+    }
+}
+
+/// What's expected of a single reaction.
+///
+/// `C` is the change type; `V` is the type of the values it captures.
+pub struct Matcher<C, V = C> {
+    kind: Kind<C, V>,
+}
+
+type Predicate<C, V> = Box<dyn Fn(&C, &Captures<V>) -> bool>;
+type Capture<C, V> = Box<dyn Fn(&C) -> Option<V>>;
+type Using<C, V> = Box<dyn Fn(&Captures<V>) -> Matcher<C, V>>;
+
+enum Kind<C, V> {
+    Exact(C),
+    Any,
+    Predicate(String, Predicate<C, V>),
+    AnyOf(Vec<Matcher<C, V>>),
+    Capture(String, Capture<C, V>),
+    Using(Using<C, V>),
+}
+
+impl<C, V> Matcher<C, V> {
+    /// Exactly this change.
+    pub fn exact(change: C) -> Self {
+        Self {
+            kind: Kind::Exact(change),
+        }
+    }
+
+    /// Any change at all (a wildcard).
+    pub fn any() -> Self {
+        Self { kind: Kind::Any }
+    }
+
+    /// Any change for which `holds`, which may look at the captures.
+    /// The `description` says what's expected, in faults.
+    pub fn predicate(
+        description: impl Into<String>,
+        holds: impl Fn(&C, &Captures<V>) -> bool + 'static,
+    ) -> Self {
+        Self {
+            kind: Kind::Predicate(description.into(), Box::new(holds)),
+        }
+    }
+
+    /// Whatever matches any of these (the first one that does, if they capture).
+    pub fn any_of(matchers: impl IntoIterator<Item = Self>) -> Self {
+        Self {
+            kind: Kind::AnyOf(matchers.into_iter().collect()),
+        }
+    }
+
+    /// Any change from which `extract` gets a value, which is captured as `name`.
+    pub fn capture(name: impl Into<String>, extract: impl Fn(&C) -> Option<V> + 'static) -> Self {
+        Self {
+            kind: Kind::Capture(name.into(), Box::new(extract)),
+        }
+    }
+
+    /// The matcher `make` makes out of the captures so far, when it's needed.
+    pub fn using(make: impl Fn(&Captures<V>) -> Self + 'static) -> Self {
+        Self {
+            kind: Kind::Using(Box::new(make)),
+        }
+    }
+}
+
+impl<C: PartialEq, V: Clone> Matcher<C, V> {
+    /// Whether the change matches. If it does, its captures are added to `captures`;
+    /// if it doesn't, `captures` is left alone.
+    pub fn matches(&self, change: &C, captures: &mut Captures<V>) -> bool {
+        match &self.kind {
+            Kind::Exact(c) => c == change,
+            Kind::Any => true,
+            Kind::Predicate(_, holds) => holds(change, captures),
+            Kind::AnyOf(matchers) => matchers.iter().any(|m| {
+                let mut tried = captures.clone();
+                let ok = m.matches(change, &mut tried);
+                if ok {
+                    *captures = tried;
+                }
+                ok
+            }),
+            Kind::Capture(name, extract) => match extract(change) {
+                Some(v) => {
+                    captures.insert(name.clone(), v);
+                    true
+                }
+                None => false,
+            },
+            Kind::Using(make) => make(captures).matches(change, captures),
+        }
+    }
+}
+
+impl<C: Debug, V> Matcher<C, V> {
+    /// Say what's expected, given the captures so far.
+    pub fn describe(&self, captures: &Captures<V>) -> String {
+        match &self.kind {
+            Kind::Exact(c) => format!("{c:?}"),
+            Kind::Any => String::from("anything"),
+            Kind::Predicate(description, _) => description.clone(),
+            Kind::AnyOf(matchers) => {
+                let all: Vec<_> = matchers.iter().map(|m| m.describe(captures)).collect();
+                format!("any of [{}]", all.join(", "))
+            }
+            Kind::Capture(name, _) => format!("something to capture as {name:?}"),
+            Kind::Using(make) => make(captures).describe(captures),
+        }
+    }
+}
+
+impl<C: Debug, V> Debug for Matcher<C, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Kind::Exact(c) => f.debug_tuple("Exact").field(c).finish(),
+            Kind::Any => f.write_str("Any"),
+            Kind::Predicate(description, _) => {
+                f.debug_tuple("Predicate").field(description).finish()
+            }
+            Kind::AnyOf(matchers) => f.debug_tuple("AnyOf").field(matchers).finish(),
+            Kind::Capture(name, _) => f.debug_tuple("Capture").field(name).finish(),
+            Kind::Using(_) => f.write_str("Using(..)"),
+        }
+    }
+}
+
+impl<C, V> From<C> for Matcher<C, V> {
+    fn from(change: C) -> Self {
+        Self::exact(change)
+    }
+}
+
+/// Why a batch of reactions didn't match.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Unmatched<C> {
+    /// Index of the reaction in the batch (or, if it's missing, where it should have been).
+    pub index: usize,
+    /// What was expected, or `None` if nothing more was.
+    pub expected: Option<String>,
+    /// What came, or `None` if nothing more did.
+    pub actual: Option<C>,
+}
+
+impl<C: Debug> Display for Unmatched<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.expected, &self.actual) {
+            (Some(e), Some(a)) => write!(f, "expected {e}, got {a:?}")?,
+            (Some(e), None) => write!(f, "expected {e}, got nothing")?,
+            (None, Some(a)) => write!(f, "expected nothing, got {a:?}")?,
+            (None, None) => write!(f, "no mismatch")?,
+        }
+        write!(f, " (reaction {})", self.index)
+    }
+}
+
+/// Match a batch of reactions, one for one, against the matchers,
+/// in order or in any order.
+///
+/// On success, the captures of all the matchers are added to `captures`;
+/// otherwise, `captures` is left alone, and the first mismatch is returned.
+pub fn match_batch<C, V>(
+    matchers: &[Matcher<C, V>],
+    reactions: &[C],
+    batching: Batching,
+    captures: &mut Captures<V>,
+) -> Result<(), Unmatched<C>>
+where
+    C: PartialEq + Debug + Clone,
+    V: Clone,
+{
+    let mut tried = captures.clone();
+    let ok = match batching {
+        Batching::InOrder => {
+            matchers.len() == reactions.len()
+                && matchers
+                    .iter()
+                    .zip(reactions)
+                    .all(|(m, r)| m.matches(r, &mut tried))
+        }
+        Batching::AnyOrder => {
+            let mut used = vec![false; reactions.len()];
+            matchers.len() == reactions.len() && assign(matchers, reactions, &mut used, &mut tried)
+        }
+    };
+    if ok {
+        *captures = tried;
+        return Ok(());
+    }
+    Err(explain(matchers, reactions, batching, captures))
+}
+
+/// Find reactions for the matchers, one each, backtracking if need be.
+fn assign<C, V>(
+    matchers: &[Matcher<C, V>],
+    reactions: &[C],
+    used: &mut [bool],
+    captures: &mut Captures<V>,
+) -> bool
+where
+    C: PartialEq,
+    V: Clone,
+{
+    let Some((m, rest)) = matchers.split_first() else {
+        return true;
+    };
+    for (i, r) in reactions.iter().enumerate() {
+        if used[i] {
+            continue;
+        }
+        let mut tried = captures.clone();
+        if m.matches(r, &mut tried) {
+            used[i] = true;
+            if assign(rest, reactions, used, &mut tried) {
+                *captures = tried;
+                return true;
+            }
+            used[i] = false;
+        }
+    }
+    false
+}
+
+/// Point out the first mismatch, greedily.
+fn explain<C, V>(
+    matchers: &[Matcher<C, V>],
+    reactions: &[C],
+    batching: Batching,
+    captures: &Captures<V>,
+) -> Unmatched<C>
+where
+    C: PartialEq + Debug + Clone,
+    V: Clone,
+{
+    let mut captures = captures.clone();
+    let mut used = vec![false; reactions.len()];
+    for (index, m) in matchers.iter().enumerate() {
+        let found = match batching {
+            Batching::InOrder => reactions
+                .get(index)
+                .filter(|r| m.matches(r, &mut captures))
+                .map(|_| index),
+            Batching::AnyOrder => {
+                (0..reactions.len()).find(|&i| !used[i] && m.matches(&reactions[i], &mut captures))
+            }
+        };
+        match found {
+            Some(i) => used[i] = true,
+            None => {
+                let actual = match batching {
+                    Batching::InOrder => reactions.get(index),
+                    Batching::AnyOrder => (0..reactions.len())
+                        .find(|&i| !used[i])
+                        .map(|i| &reactions[i]),
+                };
+                return Unmatched {
+                    index,
+                    expected: Some(m.describe(&captures)),
+                    actual: actual.cloned(),
+                };
+            }
+        }
+    }
+    let index = used.iter().position(|u| !u).unwrap_or(reactions.len());
+    Unmatched {
+        index,
+        expected: None,
+        actual: reactions.get(index).cloned(),
+    }
+}
+
+#[cfg(test)]
+mod test_matcher {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::{judge, Judge, Judgment};
+    use alloc::string::ToString;
+    use core::convert::Infallible;
+
+    #[test]
+    fn test_kinds() {
+        let mut caps = Captures::<StackChange>::new();
+        let even = Matcher::predicate(
+            "an even value",
+            |c, _| matches!(c, Value(Some(x)) if x % 2 == 0),
+        );
+        assert!(even.matches(&Value(Some(4)), &mut caps));
+        assert!(!even.matches(&Value(Some(3)), &mut caps));
+        assert!(Matcher::any().matches(&Pop, &mut caps));
+        let either = Matcher::any_of([Matcher::exact(Pop), even]);
+        assert!(either.matches(&Pop, &mut caps));
+        assert!(!either.matches(&Push(1), &mut caps));
+        assert_eq!(
+            either.describe(&caps),
+            "any of [Pop, an even value]".to_string()
+        );
+    }
+
+    #[test]
+    fn test_batches() {
+        let mut caps = Captures::<StackChange>::new();
+        let m = [Matcher::exact(Value(Some(1))), Matcher::any()];
+        let batch = [Value(Some(2)), Value(Some(1))];
+        // A greedy wildcard would take the 1, but there's a way.
+        let any = [Matcher::any(), Matcher::exact(Value(Some(2)))];
+        assert!(match_batch(&any, &batch, Batching::AnyOrder, &mut caps).is_ok());
+        assert!(match_batch(&m, &batch, Batching::AnyOrder, &mut caps).is_ok());
+        let e = match_batch(&m, &batch, Batching::InOrder, &mut caps).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "expected Value(Some(1)), got Value(Some(2)) (reaction 0)"
+        );
+        let e = match_batch(&m, &batch[..1], Batching::AnyOrder, &mut caps).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "expected Value(Some(1)), got Value(Some(2)) (reaction 0)"
+        );
+        let e = match_batch(&m[..1], &batch, Batching::AnyOrder, &mut caps).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "expected nothing, got Value(Some(2)) (reaction 0)"
+        );
+        let e = match_batch(&m, &batch[1..], Batching::InOrder, &mut caps).unwrap_err();
+        assert_eq!(e.to_string(), "expected anything, got nothing (reaction 1)");
+    }
+
+    /// Sessions, identified by numbers the object makes up.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Session {
+        Open,
+        Opened(u32),
+        Close(u32),
+        Closed(u32),
+    }
+    use Session::*;
+
+    /// Opens two sessions, then closes them, and checks the IDs.
+    struct Sessions {
+        caps: Captures<u32>,
+        turn: usize,
+    }
+
+    impl Judge for Sessions {
+        type Change = Session;
+        type Fault = Unmatched<Session>;
+        type Error = Infallible;
+        fn next(
+            &mut self,
+            reactions: Vec<Session>,
+        ) -> Result<Judgment<Session, Self::Fault>, Infallible> {
+            let opened = |name: &'static str| {
+                Matcher::capture(name, |c| match c {
+                    Opened(id) => Some(*id),
+                    _ => None,
+                })
+            };
+            let closed =
+                |name: &'static str| Matcher::using(move |caps| Matcher::exact(Closed(caps[name])));
+            let expect = match self.turn {
+                0 => vec![],
+                1 => vec![opened("a")],
+                2 => vec![opened("b")],
+                3 => vec![closed("a")],
+                _ => vec![closed("b")],
+            };
+            if let Err(e) = match_batch(&expect, &reactions, Batching::InOrder, &mut self.caps) {
+                return Ok(Judgment::Fault(e));
+            }
+            self.turn += 1;
+            Ok(match self.turn {
+                1 | 2 => Judgment::Continue(Open),
+                3 => Judgment::Continue(Close(self.caps["a"])),
+                4 => Judgment::Continue(Close(self.caps["b"])),
+                _ => Judgment::Done,
+            })
+        }
+    }
+
+    fn server(off_by_one: bool) -> impl FnMut(Session) -> Vec<Session> {
+        let mut next = 100;
+        move |msg| match msg {
+            Open => {
+                next += 7;
+                vec![Opened(next)]
+            }
+            Close(id) if off_by_one => vec![Closed(id + 1)],
+            Close(id) => vec![Closed(id)],
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn test_captures() {
+        let sessions = || Sessions {
+            caps: Captures::new(),
+            turn: 0,
+        };
+        let o = judge(sessions(), server(false)).unwrap();
+        assert_eq!((o.judgment, o.calls), (Judgment::Done, 4));
+        let o = judge(sessions(), server(true)).unwrap();
+        let Judgment::Fault(e) = o.judgment else {
+            panic!("no fault");
+        };
+        assert_eq!(
+            e.to_string(),
+            "expected Closed(107), got Closed(108) (reaction 0)"
+        );
+    }
+}
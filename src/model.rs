@@ -0,0 +1,492 @@
+//! Judging an object against a reference model.
+//!
+//! Most judges look the same: keep a reference implementation of the object,
+//! feed it every stimulus, and check that the object reacts the way the reference did.
+//! (That's what `StackJudge`, in the source code, does by hand.)
+//! A [`ModelJudge`] does all of that; you write the reference, as a [`Model`],
+//! and say how much slack the object gets:
+//! - [`Lag`]: how many calls late a reaction may come
+//!   (see the doctrine of the non-immediacy of reactions), and which
+//!   reactions are mere placeholders that mean "not yet";
+//! - [`Batching`]: whether reactions released together must keep their order.
+//!
+//! ```
+//! use caet::model::{Lag, Model, ModelJudge};
+//! use caet::{judge, Judgment};
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Change {
+//!     Push(i32),
+//!     Pop,
+//!     Value(Option<i32>),
+//! }
+//! use Change::*;
+//!
+//! /// The reference stack.
+//! struct Stack(Vec<i32>);
+//! impl Model<Change> for Stack {
+//!     type Error = &'static str;
+//!     fn apply(&mut self, stimulus: &Change) -> Result<Vec<Change>, &'static str> {
+//!         Ok(match stimulus {
+//!             Push(x) => {
+//!                 self.0.push(*x);
+//!                 vec![]
+//!             }
+//!             Pop => vec![Value(Some(self.0.pop().ok_or("more pops than pushes")?))],
+//!             Value(_) => return Err("Value in scenario"),
+//!         })
+//!     }
+//! }
+//!
+//! let j = ModelJudge::new(Stack(vec![]), [Push(1), Push(2), Pop, Pop])
+//!     .lag(Lag::Unbounded)
+//!     .placeholder(|c| *c == Value(None));
+//!
+//! // Hold on to every pop until the stack is empty, then release them all at once.
+//! let (mut stack, mut held) = (vec![], vec![]);
+//! let lazy = move |c| match c {
+//!     Push(x) => {
+//!         stack.push(x);
+//!         vec![]
+//!     }
+//!     _ => {
+//!         held.push(Value(stack.pop()));
+//!         if stack.is_empty() {
+//!             std::mem::take(&mut held)
+//!         } else {
+//!             vec![Value(None)]
+//!         }
+//!     }
+//! };
+//! assert_eq!(judge(j, lazy).unwrap().judgment, Judgment::Done);
+//! ```
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display};
+
+use crate::{Judge, Judgment, StepContext};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A reference implementation of the object.
+pub trait Model<C> {
+    /// Why the stimulus can't be applied (a bad scenario, say).
+    /// It becomes the judge's error.
+    type Error;
+
+    /// Apply the stimulus, and say how the object should react.
+    fn apply(&mut self, stimulus: &C) -> Result<Vec<C>, Self::Error>;
+}
+
+/// How late a reaction may come.
+///
+/// Calls are counted from the one that delivered the stimulus the reaction answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Lag {
+    /// Reactions come with the call that caused them.
+    #[default]
+    None,
+    /// Reactions may come up to this many calls late.
+    Calls(usize),
+    /// Reactions may come any time, or, if the scenario ends first, never.
+    Unbounded,
+}
+
+/// Whether the reactions released in one call must come in the model's order.
+///
+/// Across calls, order is always kept: a reaction that comes in a later call
+/// can't answer an earlier stimulus than one that came before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Batching {
+    /// In the model's order.
+    #[default]
+    InOrder,
+    /// In any order (a batch may be shuffled on its way to the judge).
+    AnyOrder,
+}
+
+/// Why a [`ModelJudge`] faulted.
+///
+/// `call` is the number of calls of the object so far.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Mismatch<C> {
+    /// The object reacted when the model didn't.
+    Unexpected {
+        /// When.
+        call: usize,
+        /// The reaction.
+        reaction: C,
+    },
+    /// The object reacted, but not the way the model did.
+    Wrong {
+        /// When.
+        call: usize,
+        /// The model's reaction.
+        expected: C,
+        /// The object's reaction.
+        reaction: C,
+    },
+    /// The object didn't react in time.
+    Late {
+        /// When it was noticed.
+        call: usize,
+        /// The model's reaction.
+        expected: C,
+        /// The call that delivered the stimulus it answers.
+        since: usize,
+    },
+}
+
+impl<C: Debug> Display for Mismatch<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Unexpected { call, reaction } => {
+                write!(f, "unexpected reaction {reaction:?} (call {call})")
+            }
+            Mismatch::Wrong {
+                call,
+                expected,
+                reaction,
+            } => write!(f, "expected {expected:?}, got {reaction:?} (call {call})"),
+            Mismatch::Late {
+                call,
+                expected,
+                since,
+            } => write!(
+                f,
+                "expected {expected:?} since call {since}, still missing at call {call}"
+            ),
+        }
+    }
+}
+
+/// A judge that plays a scenario against a [`Model`] and the object,
+/// and checks that the object reacts like the model.
+///
+/// - Sends the stimuli of the scenario, in order, to both.
+/// - Faults with a [`Mismatch`] when the object's reactions aren't the model's,
+///   within the [`Lag`] and [`Batching`] allowed.
+/// - Is done once the scenario is exhausted. (Reactions still owed then are
+///   not waited for: the judge has no stimulus left to wait with.)
+///
+/// Placeholders (see [`placeholder`](ModelJudge::placeholder)) are ignored,
+/// whoever produces them.
+///
+/// Calls are counted by the runner (see [`StepContext::call`]), so lag is only
+/// measured when it's run with [`Judge::next_with`], as every runner does.
+#[derive(Debug, Clone)]
+pub struct ModelJudge<M, C> {
+    model: M,
+    scenario: VecDeque<C>,
+    /// Reactions owed, with the call that delivered their stimulus.
+    expect: VecDeque<(C, usize)>,
+    lag: Lag,
+    batching: Batching,
+    placeholder: Option<fn(&C) -> bool>,
+}
+
+impl<M, C> ModelJudge<M, C>
+where
+    M: Model<C>,
+{
+    /// Play this scenario, with no lag, in order, and no placeholders.
+    pub fn new(model: M, scenario: impl IntoIterator<Item = C>) -> Self {
+        Self {
+            model,
+            scenario: scenario.into_iter().collect(),
+            expect: VecDeque::new(),
+            lag: Lag::None,
+            batching: Batching::InOrder,
+            placeholder: None,
+        }
+    }
+
+    /// Allow this much lag.
+    pub fn lag(mut self, lag: Lag) -> Self {
+        self.lag = lag;
+        self
+    }
+
+    /// Allow this kind of batching.
+    pub fn batching(mut self, batching: Batching) -> Self {
+        self.batching = batching;
+        self
+    }
+
+    /// Treat reactions for which `is` holds as placeholders that mean "not yet",
+    /// like `Value(None)` from a stack that hasn't decided what it popped.
+    pub fn placeholder(mut self, is: fn(&C) -> bool) -> Self {
+        self.placeholder = Some(is);
+        self
+    }
+
+    /// The model.
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    /// The model, as it was at the end.
+    pub fn into_model(self) -> M {
+        self.model
+    }
+
+    /// The reactions the object still owes, in order.
+    pub fn owed(&self) -> impl Iterator<Item = &C> + '_ {
+        self.expect.iter().map(|(c, _)| c)
+    }
+
+    fn is_placeholder(&self, c: &C) -> bool {
+        self.placeholder.is_some_and(|is| is(c))
+    }
+}
+
+impl<M, C> ModelJudge<M, C>
+where
+    M: Model<C>,
+    C: PartialEq,
+{
+    /// Match the reactions of the latest call against what's owed.
+    fn settle(&mut self, mut reactions: Vec<C>, call: usize) -> Option<Mismatch<C>> {
+        reactions.retain(|r| !self.is_placeholder(r));
+        if reactions.len() > self.expect.len() {
+            let reaction = reactions.swap_remove(self.expect.len());
+            return Some(Mismatch::Unexpected { call, reaction });
+        }
+        let mut owed: Vec<_> = self.expect.drain(..reactions.len()).collect();
+        for reaction in reactions {
+            let at = match self.batching {
+                Batching::InOrder => 0,
+                // If it's not owed at all, it's wrong with respect to the first one.
+                Batching::AnyOrder => owed.iter().position(|(c, _)| *c == reaction).unwrap_or(0),
+            };
+            let (expected, _) = owed.remove(at);
+            if expected != reaction {
+                return Some(Mismatch::Wrong {
+                    call,
+                    expected,
+                    reaction,
+                });
+            }
+        }
+        None
+    }
+}
+
+impl<M, C> Judge for ModelJudge<M, C>
+where
+    M: Model<C>,
+    C: PartialEq + Clone,
+{
+    type Change = C;
+    type Fault = Mismatch<C>;
+    type Error = M::Error;
+
+    fn next(&mut self, reactions: Vec<C>) -> Result<Judgment<C, Mismatch<C>>, M::Error> {
+        self.next_with(reactions, &mut StepContext::new(0))
+    }
+
+    fn next_with(
+        &mut self,
+        reactions: Vec<C>,
+        cx: &mut StepContext,
+    ) -> Result<Judgment<C, Mismatch<C>>, M::Error> {
+        let call = cx.call();
+        if let Some(m) = self.settle(reactions, call) {
+            return Ok(Judgment::Fault(m));
+        }
+        let late = match self.lag {
+            Lag::None => Some(0),
+            Lag::Calls(n) => Some(n),
+            Lag::Unbounded => None,
+        };
+        if let Some(n) = late {
+            if let Some((expected, since)) = self.expect.front() {
+                if call >= since + n {
+                    return Ok(Judgment::Fault(Mismatch::Late {
+                        call,
+                        expected: expected.clone(),
+                        since: *since,
+                    }));
+                }
+            }
+        }
+        let Some(stimulus) = self.scenario.pop_front() else {
+            return Ok(Judgment::Done);
+        };
+        for c in self.model.apply(&stimulus)? {
+            if !self.is_placeholder(&c) {
+                self.expect.push_back((c, call + 1));
+            }
+        }
+        Ok(Judgment::Continue(stimulus))
+    }
+}
+
+#[cfg(test)]
+mod test_model {
+    use super::*;
+    use crate::judge;
+    use crate::test_stack::*;
+    use alloc::string::{String, ToString};
+
+    /// The reference stack of `StackJudge`.
+    struct Stack(Vec<i32>);
+    impl Model<StackChange> for Stack {
+        type Error = String;
+        fn apply(&mut self, stimulus: &StackChange) -> Result<Vec<StackChange>, String> {
+            Ok(match stimulus {
+                Push(x) => {
+                    self.0.push(*x);
+                    vec![]
+                }
+                Pop => match self.0.pop() {
+                    Some(x) => vec![Value(Some(x))],
+                    None => return Err("bad sim: more pops than pushes".to_string()),
+                },
+                Value(_) => return Err("bad sim: Value in scenario".to_string()),
+            })
+        }
+    }
+
+    /// `StackJudge`, in a few lines.
+    fn stack_judge(scenario: &StackJudge) -> ModelJudge<Stack, StackChange> {
+        ModelJudge::new(Stack(vec![]), scenario.scenario.iter().copied())
+            .lag(Lag::Unbounded)
+            .placeholder(|c| *c == Value(None))
+    }
+
+    fn run(
+        j: ModelJudge<Stack, StackChange>,
+        object: impl FnMut(StackChange) -> Vec<StackChange>,
+    ) -> Result<Judgment<StackChange, Mismatch<StackChange>>, String> {
+        judge(j, object).map(|o| o.judgment)
+    }
+
+    #[test]
+    fn test_like_stack_judge() {
+        let mut stack = vec![];
+        let good = run(stack_judge(&scenario_1()), demo_impl_good(&mut stack));
+        assert_eq!(good, Ok(Judgment::Done));
+        for sce in [scenario_1(), scenario_4()] {
+            assert_eq!(run(stack_judge(&sce), demo_impl_lazy()), Ok(Judgment::Done));
+        }
+        // Silence is as good as ever, with unbounded lag.
+        let discard = run(stack_judge(&scenario_1()), demo_impl_discard());
+        assert_eq!(discard, Ok(Judgment::Done));
+        let empty = run(stack_judge(&scenario_1()), demo_impl_empty());
+        assert_eq!(empty, Ok(Judgment::Done));
+
+        let mut count = 0;
+        let zero = run(stack_judge(&scenario_1()), demo_impl_zero_smart(&mut count));
+        assert_eq!(
+            zero,
+            Ok(Judgment::Fault(Mismatch::Wrong {
+                call: 4,
+                expected: Value(Some(3)),
+                reaction: Value(Some(0)),
+            }))
+        );
+        let Ok(Judgment::Fault(irrelevant)) =
+            run(stack_judge(&scenario_1()), demo_impl_irrelevant())
+        else {
+            panic!("no fault");
+        };
+        assert_eq!(
+            irrelevant.to_string(),
+            "expected Value(Some(3)), got Push(42) (call 4)"
+        );
+        let mut stack = vec![];
+        let bad = run(stack_judge(&scenario_2()), demo_impl_good(&mut stack));
+        assert_eq!(bad, Err("bad sim: more pops than pushes".to_string()));
+    }
+
+    /// A stack that holds on to its pops until it has `n` of them.
+    fn demo_impl_batched(n: usize) -> impl FnMut(StackChange) -> Vec<StackChange> {
+        let mut stack = vec![];
+        let mut held = vec![];
+        move |msg| match msg {
+            Push(x) => {
+                stack.push(x);
+                vec![]
+            }
+            Pop => {
+                held.push(Value(stack.pop()));
+                if held.len() == n {
+                    core::mem::take(&mut held)
+                } else {
+                    vec![]
+                }
+            }
+            Value(_) => panic!("Value in demo_impl"),
+        }
+    }
+
+    fn scenario_5() -> StackJudge {
+        StackJudge::new_scenario(vec![Push(1), Push(2), Push(3), Pop, Pop, Pop])
+    }
+
+    #[test]
+    fn test_lag() {
+        // The first pop is answered two calls late.
+        let batched = |lag| run(stack_judge(&scenario_5()).lag(lag), demo_impl_batched(3));
+        assert_eq!(batched(Lag::Calls(2)), Ok(Judgment::Done));
+        assert_eq!(
+            batched(Lag::Calls(1)),
+            Ok(Judgment::Fault(Mismatch::Late {
+                call: 5,
+                expected: Value(Some(3)),
+                since: 4,
+            }))
+        );
+        assert!(matches!(
+            batched(Lag::None),
+            Ok(Judgment::Fault(Mismatch::Late { call: 4, .. }))
+        ));
+
+        // Reacting to a push is never right.
+        let mut stack = vec![];
+        let mut good = demo_impl_good(&mut stack);
+        let chatty = move |c| match c {
+            Push(x) => [good(c), vec![Push(x)]].concat(),
+            _ => good(c),
+        };
+        assert_eq!(
+            run(stack_judge(&scenario_1()), chatty),
+            Ok(Judgment::Fault(Mismatch::Unexpected {
+                call: 1,
+                reaction: Push(1),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_batching() {
+        // Releases its pops backwards.
+        let backwards = || {
+            let mut batched = demo_impl_batched(3);
+            move |c| {
+                let mut r = batched(c);
+                r.reverse();
+                r
+            }
+        };
+        let j = || stack_judge(&scenario_5());
+        assert_eq!(
+            run(j().batching(Batching::AnyOrder), backwards()),
+            Ok(Judgment::Done)
+        );
+        assert_eq!(
+            run(j(), backwards()),
+            Ok(Judgment::Fault(Mismatch::Wrong {
+                call: 6,
+                expected: Value(Some(3)),
+                reaction: Value(Some(1)),
+            }))
+        );
+    }
+}
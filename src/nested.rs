@@ -0,0 +1,470 @@
+//! Inner universes, for divide and conquer.
+//!
+//! An object may be composed of simpler, shorter-lived universes, each with its own
+//! judge and its own object (see "Divide and Conquer", in the README).
+//! A [`NestedObject`] is given a [`Universe`] along with every observation,
+//! in which it can [`run`](Universe::run) an inner simulation to the end and use
+//! its [`Exit`], or [`spawn`](Universe::spawn) one, [`step`](Inner::step) it a little
+//! on every call, and [`join`](Universe::join) it when it's done.
+//! Every inner simulation has a [`Config`] of its own (a buggy inner judge
+//! shouldn't hang the outer simulation), and a seed drawn from the universe it runs in.
+//! Inner objects can have inner universes of their own, and so on.
+//!
+//! [`judge_nested`] runs the outermost simulation, and keeps track of every inner one
+//! that was joined: the [`Nested`] result has the exit of the outer simulation,
+//! and a tree of [`Child`] outcomes underneath it. So does every [`join`](Universe::join),
+//! for its own subtree.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt::{self, Debug, Display};
+
+use crate::rng::Rng;
+use crate::{Config, Exit, Judge, Judgment, Limit, Object, Simulation};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How an inner simulation ended.
+///
+/// Inner universes have fault types of their own, so faults are kept as their
+/// `Display` representations.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Verdict {
+    /// The judge was done.
+    Done,
+    /// The judge found a fault.
+    Fault(String),
+    /// A limit in its [`Config`] was hit first.
+    Exhausted(Limit),
+}
+
+/// The outcome of an inner simulation, in the tree of outcomes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Child {
+    /// The name it was spawned with.
+    pub name: String,
+    /// How it ended.
+    pub verdict: Verdict,
+    /// Number of calls of its object.
+    pub calls: usize,
+    /// The seed of its runner's random number generator.
+    pub seed: u64,
+    /// The virtual time when it ended (zero, without a clock).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time: crate::time::Time,
+    /// The inner simulations its object joined, in order.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub children: Vec<Child>,
+}
+
+/// What an object is given, to run inner simulations in.
+#[derive(Debug)]
+pub struct Universe {
+    /// Where the seeds of inner simulations come from.
+    rng: Rng,
+    /// The inner simulations joined so far.
+    children: Vec<Child>,
+}
+
+impl Universe {
+    /// A universe with nothing in it, whose inner simulations get seeds drawn from this one.
+    ///
+    /// [`judge_nested`] makes its own; this is for calling an object by hand.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            children: Vec::new(),
+        }
+    }
+
+    /// Set up an inner simulation, to [`step`](Inner::step) at your own pace,
+    /// within the limits of a [`Config`].
+    ///
+    /// Its seed is drawn from this universe's, whatever the config says,
+    /// so the whole tree can be reproduced from the seed of the outermost simulation.
+    /// It only shows up in the tree of outcomes once it's [joined](Self::join).
+    pub fn spawn<J, O>(
+        &mut self,
+        name: impl Into<String>,
+        config: Config,
+        judge: J,
+        object: O,
+    ) -> Inner<J, O>
+    where
+        J: Judge,
+        O: NestedObject<J::Change>,
+    {
+        let config = config.seed(self.rng.next_u64());
+        Inner::new(name.into(), config, judge, object)
+    }
+
+    /// Run an inner simulation to the end (if it isn't already there),
+    /// and add it to the tree of outcomes.
+    ///
+    /// Returns how it ended, with the inner simulations it ran in turn.
+    pub fn join<J, O>(&mut self, mut inner: Inner<J, O>) -> Result<Nested<J>, J::Error>
+    where
+        J: Judge,
+        J::Fault: Display,
+        O: NestedObject<J::Change>,
+    {
+        while inner.step()? {}
+        let seed = inner.sim.config().seed;
+        let (_, (_, universe), exit) = inner.sim.into_parts();
+        let exit = exit.expect("over");
+        let (verdict, time) = match &exit {
+            Exit::Judged(o) => match &o.judgment {
+                Judgment::Fault(f) => (Verdict::Fault(f.to_string()), o.time),
+                _ => (Verdict::Done, o.time),
+            },
+            Exit::Exhausted { limit, time, .. } => (Verdict::Exhausted(*limit), *time),
+            _ => unreachable!("inner simulations neither crash nor catch panics"),
+        };
+        self.children.push(Child {
+            name: inner.name,
+            verdict,
+            calls: exit.calls(),
+            seed,
+            time,
+            children: universe.children.clone(),
+        });
+        Ok(Nested {
+            exit,
+            children: universe.children,
+        })
+    }
+
+    /// Run an inner simulation to the end, within the limits of a [`Config`],
+    /// and add it to the tree of outcomes.
+    ///
+    /// See [`spawn`](Self::spawn) and [`join`](Self::join).
+    pub fn run<J, O>(
+        &mut self,
+        name: impl Into<String>,
+        config: Config,
+        judge: J,
+        object: O,
+    ) -> Result<Nested<J>, J::Error>
+    where
+        J: Judge,
+        J::Fault: Display,
+        O: NestedObject<J::Change>,
+    {
+        let inner = self.spawn(name, config, judge, object);
+        self.join(inner)
+    }
+
+    /// The inner simulations joined so far, in order.
+    pub fn children(&self) -> &[Child] {
+        &self.children
+    }
+}
+
+/// An inner simulation, in progress.
+///
+/// See [`Universe::spawn`].
+pub struct Inner<J: Judge, O> {
+    name: String,
+    /// The object comes with the universe it runs its own inner simulations in.
+    sim: Simulation<J, (O, Universe), Infallible>,
+}
+
+/// Set apart the universe's stream from the judge's, which starts from the same seed
+/// (otherwise, the seeds of the inner simulations would be the judge's random draws).
+const UNIVERSE_STREAM: u64 = 0x6a09_e667_f3bc_c909;
+
+impl<J: Judge, O: NestedObject<J::Change>> Inner<J, O> {
+    fn new(name: String, config: Config, judge: J, object: O) -> Self {
+        let universe = Universe::new(config.seed ^ UNIVERSE_STREAM);
+        Self {
+            name,
+            sim: Simulation::build(config, judge, (object, universe)),
+        }
+    }
+
+    /// Let the judge make its next move, and, if it's a stimulus, deliver it.
+    ///
+    /// Returns `false` once the simulation is over.
+    pub fn step(&mut self) -> Result<bool, J::Error> {
+        let react =
+            |(object, universe): &mut (O, Universe), msg| Ok(object.react_nested(msg, universe));
+        Ok(self.sim.advance_by(|_| (), react)?.is_some())
+    }
+
+    /// How it ended, if it has.
+    pub fn exit(&self) -> Option<&Exit<J>> {
+        self.sim.exit()
+    }
+
+    /// Number of calls of its object so far.
+    pub fn calls(&self) -> usize {
+        self.sim.calls()
+    }
+
+    /// The name it was spawned with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<J: Judge, O> Debug for Inner<J, O>
+where
+    Exit<J>: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Inner")
+            .field("name", &self.name)
+            .field("calls", &self.sim.calls())
+            .field("exit", &self.sim.exit())
+            .finish_non_exhaustive()
+    }
+}
+
+/// An object that may run inner simulations.
+///
+/// It's implemented for every `FnMut(C, &mut Universe) -> Vec<C>` closure.
+/// For an object without inner universes, see [`Flat`].
+pub trait NestedObject<C> {
+    /// Like [`Object::react`], but with a universe to run inner simulations in.
+    fn react_nested(&mut self, observation: C, universe: &mut Universe) -> Vec<C>;
+}
+
+impl<C, F> NestedObject<C> for F
+where
+    F: FnMut(C, &mut Universe) -> Vec<C>,
+{
+    fn react_nested(&mut self, observation: C, universe: &mut Universe) -> Vec<C> {
+        self(observation, universe)
+    }
+}
+
+/// An [`Object`] without inner universes.
+pub struct Flat<O>(pub O);
+
+impl<C, O: Object<C>> NestedObject<C> for Flat<O> {
+    fn react_nested(&mut self, observation: C, _: &mut Universe) -> Vec<C> {
+        self.0.react(observation)
+    }
+}
+
+/// How a simulation ended, together with the outcomes of the inner simulations that led there.
+pub struct Nested<J: Judge> {
+    /// How the simulation ended.
+    pub exit: Exit<J>,
+    /// The inner simulations its object joined, in order.
+    pub children: Vec<Child>,
+}
+
+// (See `Traced` for why these are spelled out.)
+impl<J: Judge> Debug for Nested<J>
+where
+    Exit<J>: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Nested")
+            .field("exit", &self.exit)
+            .field("children", &self.children)
+            .finish()
+    }
+}
+impl<J: Judge> Clone for Nested<J>
+where
+    Exit<J>: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            exit: self.exit.clone(),
+            children: self.children.clone(),
+        }
+    }
+}
+impl<J: Judge> PartialEq for Nested<J>
+where
+    Exit<J>: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.exit == other.exit && self.children == other.children
+    }
+}
+impl<J: Judge> Eq for Nested<J> where Exit<J>: Eq {}
+
+/// Like [`judge_with`](crate::judge_with), but the object may run inner simulations.
+///
+/// The seeds of the inner simulations are drawn from [`Config::seed`], so
+/// the same seed gives the same tree.
+pub fn judge_nested<J, O>(config: Config, judge: J, object: O) -> Result<Nested<J>, J::Error>
+where
+    J: Judge,
+    O: NestedObject<J::Change>,
+{
+    let mut outer = Inner::new(String::new(), config, judge, object);
+    while outer.step()? {}
+    let (_, (_, universe), exit) = outer.sim.into_parts();
+    Ok(Nested {
+        exit: exit.expect("over"),
+        children: universe.children,
+    })
+}
+
+#[cfg(test)]
+mod test_nested {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::Judgment;
+    use core::convert::Infallible;
+
+    /// Counts down from `n`, and expects every number to be echoed.
+    #[derive(Debug, PartialEq)]
+    struct Countdown(u32);
+    impl Judge for Countdown {
+        type Change = u32;
+        type Fault = String;
+        type Error = Infallible;
+        fn next(&mut self, reactions: Vec<u32>) -> Result<Judgment<u32, String>, Infallible> {
+            if let Some(&r) = reactions.first() {
+                if r != self.0 {
+                    return Ok(Judgment::Fault(format!("expected {}, got {r}", self.0)));
+                }
+                self.0 -= 1;
+            }
+            Ok(match self.0 {
+                0 => Judgment::Done,
+                n => Judgment::Continue(n),
+            })
+        }
+    }
+
+    /// Echoes, and counts down from 2 in an inner universe whenever it echoes 1.
+    fn echo() -> impl FnMut(u32, &mut Universe) -> Vec<u32> {
+        |n, universe| {
+            if n == 1 {
+                universe
+                    .run("inner", Config::new(), Countdown(2), Flat(|n| vec![n]))
+                    .unwrap();
+            }
+            vec![n]
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        // A stack that counts down from every value pushed, in an inner universe.
+        let mut stack = vec![];
+        let mut good = demo_impl_good(&mut stack);
+        let object = |msg, universe: &mut Universe| {
+            if let Push(x) = msg {
+                let config = Config::new();
+                let n = universe.run("push", config, Countdown(x as u32), echo());
+                let n = n.unwrap();
+                assert_eq!(n.exit.calls(), x as usize);
+                // The inner universe's own tree comes with it.
+                assert_eq!(n.children.len(), 1);
+            }
+            good(msg)
+        };
+        let nested = judge_nested(Config::new().seed(5), scenario_1(), object).unwrap();
+        assert_eq!(nested.exit.outcome().unwrap().judgment, Judgment::Done);
+        let calls: Vec<_> = nested.children.iter().map(|c| c.calls).collect();
+        assert_eq!(calls, [1, 2, 3, 4]);
+        let inner = &nested.children[2].children;
+        assert_eq!(
+            inner,
+            &[Child {
+                name: "inner".into(),
+                verdict: Verdict::Done,
+                calls: 2,
+                seed: inner[0].seed,
+                time: 0,
+                children: vec![],
+            }]
+        );
+
+        // Every seed is drawn from the outermost one.
+        let seeds =
+            |n: &Nested<StackJudge>| -> Vec<u64> { n.children.iter().map(|c| c.seed).collect() };
+        let mut stack = vec![];
+        let mut good = demo_impl_good(&mut stack);
+        let again = judge_nested(
+            Config::new().seed(5),
+            scenario_1(),
+            |msg, u: &mut Universe| {
+                if let Push(x) = msg {
+                    u.run("push", Config::new(), Countdown(x as u32), echo())
+                        .unwrap();
+                }
+                good(msg)
+            },
+        )
+        .unwrap();
+        assert_eq!(seeds(&again), seeds(&nested));
+        assert!(seeds(&nested).windows(2).all(|w| w[0] != w[1]));
+        // ...but not the same way as the judge's random choices.
+        let mut rng = Rng::new(5);
+        let draws: Vec<u64> = (0..seeds(&nested).len()).map(|_| rng.next_u64()).collect();
+        assert!(seeds(&nested).iter().all(|s| !draws.contains(s)));
+    }
+
+    #[test]
+    fn test_step() {
+        // Steps an inner countdown once per call, and joins it when asked to.
+        let mut inner = None;
+        let object = |msg: StackChange, universe: &mut Universe| match msg {
+            Push(x) => {
+                let off_by_one = Flat(|n: u32| vec![n + u32::from(n == 1)]);
+                let judge = Countdown(x as u32);
+                inner = Some(universe.spawn("slow", Config::new(), judge, off_by_one));
+                vec![]
+            }
+            Pop => {
+                let running = inner.as_mut().unwrap();
+                running.step().unwrap();
+                vec![Value(Some(running.calls() as i32))]
+            }
+            Value(_) => {
+                let n = universe.join(inner.take().unwrap()).unwrap();
+                vec![Value(Some(n.exit.calls() as i32))]
+            }
+        };
+        let mut universe = Universe::new(0);
+        let mut object = object;
+        object(Push(3), &mut universe);
+        assert_eq!(object(Pop, &mut universe), [Value(Some(1))]);
+        assert_eq!(object(Pop, &mut universe), [Value(Some(2))]);
+        assert!(universe.children().is_empty());
+        assert_eq!(object(Value(None), &mut universe), [Value(Some(3))]);
+        assert_eq!(
+            universe.children()[0].verdict,
+            Verdict::Fault("expected 1, got 2".into())
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        // An inner judge that never stops doesn't hang the outer simulation.
+        let object = |n: u32, universe: &mut Universe| {
+            let forever = Flat(|n: u32| vec![n]);
+            let config = Config::new().max_calls(10);
+            let inner = universe.run("forever", config, Countdown(u32::MAX), forever);
+            assert_eq!(inner.unwrap().exit.calls(), 10);
+            vec![n]
+        };
+        let nested = judge_nested(Config::new().max_calls(2), Countdown(5), object).unwrap();
+        assert!(matches!(
+            nested.exit,
+            Exit::Exhausted {
+                limit: Limit::Calls,
+                calls: 2,
+                ..
+            }
+        ));
+        assert_eq!(nested.children.len(), 2);
+        assert_eq!(nested.children[0].verdict, Verdict::Exhausted(Limit::Calls));
+    }
+}
@@ -0,0 +1,189 @@
+//! Objects as first-class values.
+//!
+//! Most of the time, an object is just its reaction function, a closure of type
+//! `FnMut(C) -> Vec<C>`, and that's still fine: every such closure is an [`Object`].
+//! But if you want the runner to call your object by name, to reset it between
+//! runs, or to show its internal state when it faults, implement [`Object`]
+//! yourself, or wrap the closure in [`Named`].
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Anything that reacts to changes in its universe.
+///
+/// It's implemented for every `FnMut(C) -> Vec<C>` closure, so there's
+/// nothing to do unless you want the optional methods.
+///
+/// See also: [`judge_object`](crate::judge_object).
+pub trait Object<C> {
+    /// The reaction function: observe `observation`, and react with zero or more changes.
+    ///
+    /// See the crate documentation on the doctrines of reactions.
+    fn react(&mut self, observation: C) -> Vec<C>;
+
+    /// A name to call the object by in reports.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Go back to the initial state, as if the object had just been created.
+    /// [`minimize_reset`](crate::shrink::minimize_reset) calls it before each run.
+    ///
+    /// The default does nothing, which is only right for stateless objects.
+    fn reset(&mut self) {}
+
+    /// A human-readable description of the object's internal state, for reports.
+    fn describe_state(&self) -> Option<String> {
+        None
+    }
+}
+
+impl<C, F> Object<C> for F
+where
+    F: FnMut(C) -> Vec<C>,
+{
+    fn react(&mut self, observation: C) -> Vec<C> {
+        self(observation)
+    }
+}
+
+/// A reaction function with a name.
+///
+/// ```
+/// use caet::object::{Named, Object};
+///
+/// let mut echo = Named::new("echo", |x: i32| vec![x]);
+/// assert_eq!(echo.name(), Some("echo"));
+/// assert_eq!(echo.react(3), vec![3]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Named<F> {
+    name: String,
+    react: F,
+}
+
+impl<F> Named<F> {
+    /// Give the reaction function a name.
+    pub fn new(name: impl Into<String>, react: F) -> Self {
+        Self {
+            name: name.into(),
+            react,
+        }
+    }
+
+    /// Take the reaction function back.
+    pub fn into_inner(self) -> F {
+        self.react
+    }
+}
+
+impl<C, F> Object<C> for Named<F>
+where
+    F: FnMut(C) -> Vec<C>,
+{
+    fn react(&mut self, observation: C) -> Vec<C> {
+        (self.react)(observation)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+}
+
+/// A borrowed object.
+///
+/// `&mut O` can't be an [`Object`] by itself (it would clash with the closures),
+/// so wrap it in this instead. Every method is forwarded.
+pub struct Borrowed<'a, O: ?Sized>(pub &'a mut O);
+
+impl<C, O> Object<C> for Borrowed<'_, O>
+where
+    O: Object<C> + ?Sized,
+{
+    fn react(&mut self, observation: C) -> Vec<C> {
+        self.0.react(observation)
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.0.name()
+    }
+
+    fn reset(&mut self) {
+        self.0.reset()
+    }
+
+    fn describe_state(&self) -> Option<String> {
+        self.0.describe_state()
+    }
+}
+
+/// Describe the object for a panic message, e.g., ` (object "stack"; state: [1, 2])`.
+pub(crate) fn describe<C>(object: &impl Object<C>) -> String {
+    use core::fmt::Write;
+    let mut s = String::new();
+    match (object.name(), object.describe_state()) {
+        (None, None) => (),
+        (Some(name), None) => _ = write!(s, " (object {name:?})"),
+        (None, Some(state)) => _ = write!(s, " (object state: {state})"),
+        (Some(name), Some(state)) => _ = write!(s, " (object {name:?}; state: {state})"),
+    }
+    s
+}
+
+#[cfg(test)]
+mod test_object {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::{judge_object, judge_object_panic, Judgment};
+
+    /// A stack that knows its name and can show its contents.
+    #[derive(Default)]
+    struct Stack(Vec<i32>);
+    impl Object<StackChange> for Stack {
+        fn react(&mut self, msg: StackChange) -> Vec<StackChange> {
+            match msg {
+                Push(x) => {
+                    self.0.push(x);
+                    vec![]
+                }
+                // Off by one: forgets to remove the top.
+                Pop => vec![Value(self.0.last().copied())],
+                Value(_) => panic!("Value in demo_impl"),
+            }
+        }
+        fn name(&self) -> Option<&str> {
+            Some("sticky stack")
+        }
+        fn reset(&mut self) {
+            self.0.clear();
+        }
+        fn describe_state(&self) -> Option<String> {
+            Some(format!("{:?}", self.0))
+        }
+    }
+
+    #[test]
+    fn test_closure_is_object() {
+        let mut stack = vec![];
+        let o = judge_object(scenario_1(), demo_impl_good(&mut stack)).unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+        let o = judge_object(scenario_1(), Named::new("lazy", demo_impl_lazy())).unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "subject fault (iter count: 5) (object \"sticky stack\"; state: [1, 2, 3]): expected 2, got 3"
+    )]
+    fn test_object_panic_message() {
+        judge_object_panic(scenario_1(), Stack::default());
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut stack = Stack::default();
+        stack.react(Push(1));
+        stack.reset();
+        assert_eq!(stack.describe_state().unwrap(), "[]");
+    }
+}
@@ -0,0 +1,234 @@
+//! Property-based testing with [`proptest`].
+//!
+//! Writing scenarios by hand (`scenario_1`, `scenario_2`, ...) only tests what you
+//! thought of. Instead, describe *all* scenarios with a proptest [`Strategy`],
+//! and let proptest generate them: every generated scenario is turned into a judge,
+//! the judge is run against a fresh object, and a [`Judgment::Fault`] counts as a
+//! failure of the property. Proptest then shrinks the scenario for you, so the
+//! failure it reports is (close to) minimal.
+//!
+//! A scenario that makes the judge fail ([`Judge::Error`], such as a `Pop` without
+//! a `Push` in a stack scenario) isn't a valid test case, so it's *rejected*,
+//! not failed. Write the strategy so that most scenarios are valid, or proptest
+//! gives up after too many rejections.
+//!
+//! ```
+//! use caet::property::{check_scenarios, check_scenarios_with};
+//! use caet::{Judge, Judgment};
+//! use proptest::prelude::*;
+//! use proptest::test_runner::{Config, TestError, TestRng, TestRunner};
+//! use std::convert::Infallible;
+//!
+//! /// Sends the numbers and expects them echoed back.
+//! struct Echo(Vec<u8>, Option<u8>);
+//! impl Judge for Echo {
+//!     type Change = u8;
+//!     type Fault = String;
+//!     type Error = Infallible;
+//!     fn next(&mut self, reactions: Vec<u8>) -> Result<Judgment<u8, String>, Infallible> {
+//!         if reactions != self.1.take().into_iter().collect::<Vec<_>>() {
+//!             return Ok(Judgment::Fault(format!("got {reactions:?}")));
+//!         }
+//!         Ok(match self.0.pop() {
+//!             Some(x) => Judgment::Continue(*self.1.insert(x)),
+//!             None => Judgment::Done,
+//!         })
+//!     }
+//! }
+//!
+//! let scenarios = proptest::collection::vec(any::<u8>(), 0..20);
+//! // A faithful echo passes...
+//! check_scenarios(&scenarios, |s| Echo(s, None), || |x| vec![x]).unwrap();
+//! // ...but one that can't count past 99 doesn't, and the scenario shrinks to
+//! // a single number past 99. (A seeded runner, without failure persistence,
+//! // shrinks the same way every time, and leaves no `proptest-regressions` files behind.)
+//! let config = Config { failure_persistence: None, ..Config::default() };
+//! let rng = TestRng::from_seed(config.rng_algorithm, &[1; 32]);
+//! let mut runner = TestRunner::new_with_rng(config, rng);
+//! let buggy = || |x: u8| vec![x.min(99)];
+//! let err = check_scenarios_with(&mut runner, &scenarios, |s| Echo(s, None), buggy);
+//! let Err(TestError::Fail(_, scenario)) = err else { panic!("{err:?}") };
+//! assert!(matches!(scenario[..], [x] if x > 99), "{scenario:?}");
+//! ```
+//!
+//! Inside a `proptest!` block, use [`check`] instead.
+//!
+//! Requires the `proptest` feature.
+
+use alloc::format;
+use core::fmt::Debug;
+
+use proptest::strategy::Strategy;
+use proptest::test_runner::{TestCaseError, TestError, TestRunner};
+
+use crate::{judge_object, Judge, Judgment, Object};
+
+/// Run the judge against the object, as a proptest test case.
+///
+/// A [`Judgment::Fault`] fails the test case; a [`Judge::Error`] rejects it.
+///
+/// ```
+/// use caet::property::check;
+/// # use caet::{Judge, Judgment};
+/// # use std::convert::Infallible;
+/// # struct Count(u8, u8);
+/// # impl Judge for Count {
+/// #     type Change = u8;
+/// #     type Fault = String;
+/// #     type Error = Infallible;
+/// #     fn next(&mut self, r: Vec<u8>) -> Result<Judgment<u8, String>, Infallible> {
+/// #         if !r.is_empty() { return Ok(Judgment::Fault("noisy".into())); }
+/// #         self.1 += 1;
+/// #         Ok(if self.1 <= self.0 { Judgment::Continue(self.1) } else { Judgment::Done })
+/// #     }
+/// # }
+/// use proptest::prelude::*;
+///
+/// proptest! {
+///     fn quiet(n in 0..10u8) {
+///         check(Count(n, 0), |_| vec![])?;
+///     }
+/// }
+/// quiet();
+/// ```
+pub fn check<J, O>(judge: J, object: O) -> Result<(), TestCaseError>
+where
+    J: Judge,
+    J::Fault: Debug,
+    J::Error: Debug,
+    O: Object<J::Change>,
+{
+    match judge_object(judge, object) {
+        Ok(o) => match o.judgment {
+            Judgment::Fault(fault) => Err(TestCaseError::fail(format!(
+                "fault after {} calls: {fault:?}",
+                o.calls
+            ))),
+            _ => Ok(()),
+        },
+        Err(error) => Err(TestCaseError::reject(format!("judge failed: {error:?}"))),
+    }
+}
+
+/// Generate scenarios from the strategy, and [`check`] each one with a judge
+/// made by `make_judge` and a fresh object made by `make_object`.
+///
+/// Uses proptest's default configuration (which honors the `PROPTEST_*`
+/// environment variables); see [`check_scenarios_with`] to use your own runner.
+///
+/// On failure, the error holds the minimal failing scenario proptest could find.
+pub fn check_scenarios<S, J, O>(
+    strategy: &S,
+    make_judge: impl Fn(S::Value) -> J,
+    make_object: impl Fn() -> O,
+) -> Result<(), TestError<S::Value>>
+where
+    S: Strategy,
+    J: Judge,
+    J::Fault: Debug,
+    J::Error: Debug,
+    O: Object<J::Change>,
+{
+    check_scenarios_with(
+        &mut TestRunner::default(),
+        strategy,
+        make_judge,
+        make_object,
+    )
+}
+
+/// Like [`check_scenarios`], but with the given runner.
+pub fn check_scenarios_with<S, J, O>(
+    runner: &mut TestRunner,
+    strategy: &S,
+    make_judge: impl Fn(S::Value) -> J,
+    make_object: impl Fn() -> O,
+) -> Result<(), TestError<S::Value>>
+where
+    S: Strategy,
+    J: Judge,
+    J::Fault: Debug,
+    J::Error: Debug,
+    O: Object<J::Change>,
+{
+    runner.run(strategy, |scenario| {
+        check(make_judge(scenario), make_object())
+    })
+}
+
+#[cfg(test)]
+mod test_property {
+    use super::*;
+    use crate::test_stack::*;
+    use alloc::vec::Vec;
+    use proptest::prelude::*;
+    use proptest::test_runner::{Config, TestRng};
+
+    /// Mostly pushes, so that most scenarios are valid.
+    fn scenarios() -> impl Strategy<Value = Vec<StackChange>> {
+        let change = prop_oneof![3 => (0..10i32).prop_map(Push), 1 => Just(Pop)];
+        proptest::collection::vec(change, 0..12)
+    }
+
+    /// Seeded, so that shrinking always ends up in the same place
+    /// (which, from an unlucky start, can be a larger scenario than the smallest one).
+    fn runner() -> TestRunner {
+        let config = Config {
+            failure_persistence: None,
+            ..Config::default()
+        };
+        let rng = TestRng::from_seed(config.rng_algorithm, &[1; 32]);
+        TestRunner::new_with_rng(config, rng)
+    }
+
+    #[test]
+    fn test_good_passes() {
+        let r = check_scenarios_with(
+            &mut runner(),
+            &scenarios(),
+            StackJudge::new_scenario,
+            || {
+                let mut stack = Vec::new();
+                move |msg| demo_impl_good(&mut stack)(msg)
+            },
+        );
+        assert_eq!(r, Ok(()));
+    }
+
+    #[test]
+    fn test_zero_smart_shrinks() {
+        let r = check_scenarios_with(
+            &mut runner(),
+            &scenarios(),
+            StackJudge::new_scenario,
+            || {
+                let mut count = 0;
+                move |msg| demo_impl_zero_smart(&mut count)(msg)
+            },
+        );
+        match r {
+            // Smallest failure: push anything but zero, and pop it.
+            // (Which number depends on where proptest started, and how it shrinks.)
+            Err(TestError::Fail(why, scenario)) => {
+                assert!(
+                    matches!(scenario[..], [Push(x), Pop] if x != 0),
+                    "{scenario:?}"
+                );
+                assert!(why.message().contains("fault after 2 calls"), "{why}");
+                let mut count = 0;
+                let r = check(
+                    StackJudge::new_scenario(scenario),
+                    demo_impl_zero_smart(&mut count),
+                );
+                assert!(matches!(r, Err(TestCaseError::Fail(_))), "{r:?}");
+            }
+            r => panic!("expected a failure, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn test_judge_error_rejects() {
+        let r = check(StackJudge::new_scenario(vec![Pop]), demo_impl_discard());
+        assert!(matches!(r, Err(TestCaseError::Reject(_))));
+    }
+}
@@ -0,0 +1,153 @@
+//! Replaying a recorded [`Transcript`] against another object.
+//!
+//! The judge that found a fault may be randomized or stateful, so running it
+//! again doesn't necessarily reproduce the fault. A [`ReplayJudge`] doesn't
+//! think for itself: it re-issues the recorded stimuli, in order, and checks
+//! that the object reacts exactly the way it did in the recording.
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt::{self, Debug, Display};
+
+use crate::trace::{Step, Transcript};
+use crate::{Judge, Judgment};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The first place where the replayed object's reactions differ from the recording.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReplayMismatch<C> {
+    /// Index of the diverging step in the transcript (zero-based).
+    pub step: usize,
+    /// The stimulus that was sent at that step.
+    pub stimulus: C,
+    /// The recorded reactions.
+    pub expected: Vec<C>,
+    /// The reactions the replayed object produced instead.
+    pub actual: Vec<C>,
+}
+
+impl<C: Debug> Display for ReplayMismatch<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "diverged at step {} (stimulus {:?}): expected {:?}, got {:?}",
+            self.step, self.stimulus, self.expected, self.actual
+        )
+    }
+}
+
+/// A judge that replays a recorded [`Transcript`].
+///
+/// - Sends the recorded stimuli, in order, regardless of what the object does.
+/// - Faults with a [`ReplayMismatch`] as soon as the object's reactions
+///   differ from the recorded ones.
+/// - Is done once the transcript is exhausted.
+///
+/// See also: [`judge_traced`](crate::trace::judge_traced).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReplayJudge<C> {
+    /// The recording.
+    steps: Vec<Step<C>>,
+    /// Number of stimuli sent so far.
+    sent: usize,
+}
+
+impl<C> ReplayJudge<C> {
+    /// Replay this transcript.
+    pub fn new(transcript: Transcript<C>) -> Self {
+        Self {
+            steps: transcript.steps,
+            sent: 0,
+        }
+    }
+
+    /// Number of stimuli sent so far.
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    /// Give back the recording.
+    pub fn into_transcript(self) -> Transcript<C> {
+        self.steps.into()
+    }
+}
+
+impl<C> From<Transcript<C>> for ReplayJudge<C> {
+    fn from(transcript: Transcript<C>) -> Self {
+        Self::new(transcript)
+    }
+}
+
+impl<C: Clone + PartialEq> Judge for ReplayJudge<C> {
+    type Change = C;
+    type Fault = ReplayMismatch<C>;
+    type Error = Infallible;
+
+    fn next(&mut self, reactions: Vec<C>) -> Result<Judgment<C, ReplayMismatch<C>>, Infallible> {
+        // The initial call (empty vector) doesn't answer any stimulus.
+        if let Some(last) = self.sent.checked_sub(1) {
+            let rec = &self.steps[last];
+            if rec.reactions != reactions {
+                return Ok(Judgment::Fault(ReplayMismatch {
+                    step: last,
+                    stimulus: rec.stimulus.clone(),
+                    expected: rec.reactions.clone(),
+                    actual: reactions,
+                }));
+            }
+        }
+        match self.steps.get(self.sent) {
+            Some(rec) => {
+                self.sent += 1;
+                Ok(Judgment::Continue(rec.stimulus.clone()))
+            }
+            None => Ok(Judgment::Done),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_replay {
+    use super::*;
+    use crate::judge;
+    use crate::test_stack::*;
+    use crate::trace::judge_traced;
+
+    fn recording() -> Transcript<StackChange> {
+        let mut stack = vec![];
+        judge_traced(scenario_1(), demo_impl_good(&mut stack))
+            .unwrap()
+            .transcript
+    }
+
+    #[test]
+    fn test_replay_same() {
+        let mut stack = vec![];
+        let o = judge(ReplayJudge::new(recording()), demo_impl_good(&mut stack)).unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+        assert_eq!(o.calls, 7);
+    }
+
+    #[test]
+    fn test_replay_diverge() {
+        let mut count = 0;
+        let o = judge(
+            ReplayJudge::new(recording()),
+            demo_impl_zero_smart(&mut count),
+        )
+        .unwrap();
+        assert_eq!(
+            o.judgment,
+            Judgment::Fault(ReplayMismatch {
+                step: 3,
+                stimulus: Pop,
+                expected: vec![Value(Some(3))],
+                actual: vec![Value(Some(0))],
+            })
+        );
+        assert_eq!(o.calls, 4);
+    }
+}
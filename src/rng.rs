@@ -0,0 +1,113 @@
+//! A small, seedable random number generator.
+//!
+//! Adversarial judges and unreliable wires need randomness, and reproducing
+//! a failure needs that randomness to be deterministic. [`Rng`] is a
+//! [SplitMix64](https://prng.di.unimi.it/splitmix64.c) generator: tiny, fast,
+//! `no_std`, and good enough for simulations (but not for cryptography).
+//! The same seed always produces the same sequence, on every platform.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A seedable pseudo-random number generator.
+///
+/// ```
+/// use caet::rng::Rng;
+///
+/// let mut a = Rng::new(42);
+/// let mut b = Rng::new(42);
+/// assert_eq!(a.next_u64(), b.next_u64());
+/// assert!(a.below(6) < 6);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    /// A generator that starts from this seed.
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    /// The seed this generator started from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Uniformly random 64 bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly random in `0..n`.
+    ///
+    /// Panics if `n` is zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0, "empty range");
+        // (Lemire's multiply-shift; the bias is negligible for simulation purposes.)
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    /// Uniformly random in `[0, 1)`.
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// `true` with probability `p` (clamped to `[0, 1]`).
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.unit() < p
+    }
+
+    /// A random element, or `None` if the slice is empty.
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            None
+        } else {
+            Some(&items[self.below(items.len())])
+        }
+    }
+
+    /// Shuffle in place (Fisher–Yates).
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_rng {
+    use super::*;
+
+    #[test]
+    fn test_reference_values() {
+        // From the reference implementation, seeded with 1234567.
+        let mut r = Rng::new(1234567);
+        assert_eq!(r.next_u64(), 6457827717110365317);
+        assert_eq!(r.next_u64(), 3203168211198807973);
+        assert_eq!(r.seed(), 1234567);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut r = Rng::new(0);
+        for _ in 0..1000 {
+            assert!(r.below(3) < 3);
+            let u = r.unit();
+            assert!((0.0..1.0).contains(&u));
+        }
+        assert!(!r.chance(0.0));
+        assert!(r.chance(1.0));
+        let mut v = [1, 2, 3, 4, 5];
+        r.shuffle(&mut v);
+        v.sort();
+        assert_eq!(v, [1, 2, 3, 4, 5]);
+    }
+}
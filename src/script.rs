@@ -0,0 +1,631 @@
+//! Scripted judges: send this, expect that.
+//!
+//! Many judges don't think at all; they send a fixed sequence of stimuli and
+//! check the reactions against a fixed list. Instead of implementing [`Judge`]
+//! for each of them, write a [`Script`], or, shorter still, use [`scenario!`](crate::scenario!):
+//!
+//! ```
+//! use caet::{judge_panic, scenario};
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Change {
+//!     Push(i32),
+//!     Pop,
+//!     Value(Option<i32>),
+//! }
+//! use Change::*;
+//!
+//! let script = scenario! {
+//!     send Push(1);
+//!     expect_nothing;
+//!     send Push(2);
+//!     send Pop;
+//!     expect Value(Some(2));
+//!     send Pop;
+//!     send Pop;
+//!     expect_any_order Value(None);
+//! };
+//!
+//! let mut stack = vec![];
+//! let calls = judge_panic(script, move |c| match c {
+//!     Push(x) => {
+//!         stack.push(x);
+//!         vec![]
+//!     }
+//!     _ => vec![Value(stack.pop())],
+//! });
+//! assert_eq!(calls, 5);
+//! ```
+//!
+//! Reactions are matched against the `expect` lines that follow the `send` that caused them.
+//! Those that no line asks about are ignored, unless a line says `expect_nothing`.
+//! The exception is `expect_within(n)`, for reactions that may come late:
+//! each of them may come with any of the next `n` calls (or already have come).
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt::{self, Debug, Display};
+
+use crate::{Judge, Judgment, StepContext};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A line of a [`Script`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Line<C> {
+    /// Send this stimulus.
+    Send(C),
+    /// The last call produced these reactions, in this order.
+    Expect(Vec<C>),
+    /// The last call produced these reactions, in any order.
+    ExpectAnyOrder(Vec<C>),
+    /// The last call produced no more reactions than those already expected.
+    ExpectNothing,
+    /// Each of these reactions comes within so many calls.
+    ExpectWithin(usize, Vec<C>),
+}
+
+/// Why a [`Script`] faulted.
+///
+/// `line` is the (one-based) line of the script that wasn't lived up to;
+/// `call` is the number of calls of the object so far.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Deviation<C> {
+    /// An expected reaction didn't come.
+    Missing {
+        /// Which line.
+        line: usize,
+        /// When.
+        call: usize,
+        /// The reaction.
+        expected: C,
+    },
+    /// A reaction came, but not the expected one.
+    Wrong {
+        /// Which line.
+        line: usize,
+        /// When.
+        call: usize,
+        /// The expected reaction.
+        expected: C,
+        /// The one that came instead.
+        reaction: C,
+    },
+    /// Reactions came when none were expected.
+    Unexpected {
+        /// Which line.
+        line: usize,
+        /// When.
+        call: usize,
+        /// The reactions.
+        reactions: Vec<C>,
+    },
+    /// An expected reaction didn't come in time (or before the script ended).
+    Late {
+        /// Which line.
+        line: usize,
+        /// When.
+        call: usize,
+        /// The reaction.
+        expected: C,
+    },
+}
+
+impl<C: Debug> Display for Deviation<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Deviation::Missing {
+                line,
+                call,
+                expected,
+            } => write!(
+                f,
+                "line {line} (call {call}): expected {expected:?}, got nothing"
+            ),
+            Deviation::Wrong {
+                line,
+                call,
+                expected,
+                reaction,
+            } => write!(
+                f,
+                "line {line} (call {call}): expected {expected:?}, got {reaction:?}"
+            ),
+            Deviation::Unexpected {
+                line,
+                call,
+                reactions,
+            } => write!(
+                f,
+                "line {line} (call {call}): expected nothing, got {reactions:?}"
+            ),
+            Deviation::Late {
+                line,
+                call,
+                expected,
+            } => write!(
+                f,
+                "line {line} (call {call}): expected {expected:?}, but it's too late"
+            ),
+        }
+    }
+}
+
+/// A reaction expected within a number of calls.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Obligation<C> {
+    line: usize,
+    expected: C,
+    /// The last call whose reactions may hold it.
+    deadline: usize,
+}
+
+/// A judge that follows a script of [`Line`]s.
+///
+/// - Sends the stimuli, in order.
+/// - After each, checks the reactions against the lines up to the next `send`,
+///   and faults with a [`Deviation`] if they don't match.
+/// - Is done at the end of the script, as long as nothing it expects within a
+///   number of calls is still missing.
+///
+/// See the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Script<C> {
+    lines: VecDeque<Line<C>>,
+    /// Number of lines read so far.
+    line: usize,
+    /// Reactions of the latest call, not yet matched.
+    seen: VecDeque<C>,
+    within: Vec<Obligation<C>>,
+}
+
+impl<C> Default for Script<C> {
+    fn default() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            line: 0,
+            seen: VecDeque::new(),
+            within: Vec::new(),
+        }
+    }
+}
+
+impl<C> From<Vec<Line<C>>> for Script<C> {
+    fn from(lines: Vec<Line<C>>) -> Self {
+        Self {
+            lines: lines.into(),
+            ..Self::default()
+        }
+    }
+}
+
+impl<C> Script<C> {
+    /// An empty script.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a line.
+    pub fn line(mut self, line: Line<C>) -> Self {
+        self.lines.push_back(line);
+        self
+    }
+
+    /// Send this stimulus.
+    pub fn send(self, stimulus: C) -> Self {
+        self.line(Line::Send(stimulus))
+    }
+
+    /// Expect these reactions, in this order.
+    pub fn expect(self, reactions: impl IntoIterator<Item = C>) -> Self {
+        self.line(Line::Expect(reactions.into_iter().collect()))
+    }
+
+    /// Expect these reactions, in any order.
+    pub fn expect_any_order(self, reactions: impl IntoIterator<Item = C>) -> Self {
+        self.line(Line::ExpectAnyOrder(reactions.into_iter().collect()))
+    }
+
+    /// Expect no other reactions.
+    pub fn expect_nothing(self) -> Self {
+        self.line(Line::ExpectNothing)
+    }
+
+    /// Expect each of these reactions within `calls` calls.
+    pub fn expect_within(self, calls: usize, reactions: impl IntoIterator<Item = C>) -> Self {
+        self.line(Line::ExpectWithin(calls, reactions.into_iter().collect()))
+    }
+
+    /// Number of lines read so far.
+    pub fn lines_read(&self) -> usize {
+        self.line
+    }
+}
+
+impl<C: PartialEq> Script<C> {
+    /// Take this reaction out of the latest ones, if it's there.
+    fn take(&mut self, expected: &C) -> bool {
+        match self.seen.iter().position(|r| r == expected) {
+            Some(i) => {
+                self.seen.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn match_in_order(&mut self, expected: Vec<C>, call: usize) -> Option<Deviation<C>> {
+        let line = self.line;
+        for expected in expected {
+            match self.seen.pop_front() {
+                None => {
+                    return Some(Deviation::Missing {
+                        line,
+                        call,
+                        expected,
+                    })
+                }
+                Some(reaction) if reaction != expected => {
+                    return Some(Deviation::Wrong {
+                        line,
+                        call,
+                        expected,
+                        reaction,
+                    })
+                }
+                Some(_) => (),
+            }
+        }
+        None
+    }
+
+    fn match_any_order(&mut self, expected: Vec<C>, call: usize) -> Option<Deviation<C>> {
+        let line = self.line;
+        let n = expected.len().min(self.seen.len());
+        let mut batch: Vec<_> = self.seen.drain(..n).collect();
+        let unmatched: Vec<_> = expected
+            .into_iter()
+            .filter(|e| {
+                let i = batch.iter().position(|r| r == e);
+                i.map(|i| batch.swap_remove(i)).is_none()
+            })
+            .collect();
+        let expected = unmatched.into_iter().next()?;
+        Some(match batch.into_iter().next() {
+            Some(reaction) => Deviation::Wrong {
+                line,
+                call,
+                expected,
+                reaction,
+            },
+            None => Deviation::Missing {
+                line,
+                call,
+                expected,
+            },
+        })
+    }
+
+    /// Read one line, after `call` calls, and say whether to send a stimulus or fault.
+    fn read(&mut self, line: Line<C>, call: usize) -> Option<Judgment<C, Deviation<C>>> {
+        let at = self.line;
+        let fault = match line {
+            Line::Send(stimulus) => return Some(Judgment::Continue(stimulus)),
+            Line::Expect(expected) => self.match_in_order(expected, call),
+            Line::ExpectAnyOrder(expected) => self.match_any_order(expected, call),
+            Line::ExpectNothing if self.seen.is_empty() => None,
+            Line::ExpectNothing => Some(Deviation::Unexpected {
+                line: at,
+                call,
+                reactions: self.seen.drain(..).collect(),
+            }),
+            Line::ExpectWithin(n, expected) => {
+                let mut fault = None;
+                for expected in expected {
+                    if self.take(&expected) {
+                        continue;
+                    }
+                    if n == 0 {
+                        fault = Some(Deviation::Missing {
+                            line: at,
+                            call,
+                            expected,
+                        });
+                        break;
+                    }
+                    self.within.push(Obligation {
+                        line: at,
+                        expected,
+                        deadline: call + n,
+                    });
+                }
+                fault
+            }
+        };
+        fault.map(Judgment::Fault)
+    }
+}
+
+impl<C: PartialEq> Judge for Script<C> {
+    type Change = C;
+    type Fault = Deviation<C>;
+    type Error = Infallible;
+
+    fn next(&mut self, reactions: Vec<C>) -> Result<Judgment<C, Deviation<C>>, Infallible> {
+        self.next_with(reactions, &mut StepContext::new(0))
+    }
+
+    fn next_with(
+        &mut self,
+        reactions: Vec<C>,
+        cx: &mut StepContext,
+    ) -> Result<Judgment<C, Deviation<C>>, Infallible> {
+        let call = cx.call();
+        self.seen = reactions.into();
+        // Late reactions are taken first, oldest obligation first.
+        let within = core::mem::take(&mut self.within);
+        for o in within {
+            if self.take(&o.expected) {
+                continue;
+            }
+            if call >= o.deadline {
+                return Ok(Judgment::Fault(Deviation::Late {
+                    line: o.line,
+                    call,
+                    expected: o.expected,
+                }));
+            }
+            self.within.push(o);
+        }
+        while let Some(line) = self.lines.pop_front() {
+            self.line += 1;
+            if let Some(j) = self.read(line, call) {
+                return Ok(j);
+            }
+        }
+        // No more calls, so whatever is still owed won't come.
+        Ok(match self.within.drain(..).next() {
+            Some(o) => Judgment::Fault(Deviation::Late {
+                line: o.line,
+                call,
+                expected: o.expected,
+            }),
+            None => Judgment::Done,
+        })
+    }
+}
+
+/// Write a [`Script`](crate::script::Script) one line at a time.
+///
+/// Every line ends with a semicolon, and is one of:
+/// - `send STIMULUS;`
+/// - `expect REACTION, ...;` (in this order)
+/// - `expect_any_order REACTION, ...;`
+/// - `expect_nothing;`
+/// - `expect_within(CALLS) REACTION, ...;`
+///
+/// See the [`script`](crate::script) module for what they mean, and an example.
+/// Anything else, including a line without its semicolon, is a compile error:
+///
+/// ```compile_fail
+/// let script = caet::scenario! {
+///     send 1u32;
+///     expcet 2u32;
+/// };
+/// ```
+///
+/// Every line is a level of macro recursion, so a script of more than a hundred lines
+/// or so needs a higher `#![recursion_limit]` in the crate that writes it
+/// (or the methods of [`Script`](crate::script::Script), which have no limit).
+#[macro_export]
+macro_rules! scenario {
+    (@ $script:expr;) => { $script };
+    (@ $script:expr; send $stimulus:expr; $($rest:tt)*) => {
+        $crate::scenario!(@ $script.send($stimulus); $($rest)*)
+    };
+    (@ $script:expr; expect $($reaction:expr),+; $($rest:tt)*) => {
+        $crate::scenario!(@ $script.expect([$($reaction),+]); $($rest)*)
+    };
+    (@ $script:expr; expect_any_order $($reaction:expr),+; $($rest:tt)*) => {
+        $crate::scenario!(@ $script.expect_any_order([$($reaction),+]); $($rest)*)
+    };
+    (@ $script:expr; expect_nothing; $($rest:tt)*) => {
+        $crate::scenario!(@ $script.expect_nothing(); $($rest)*)
+    };
+    (@ $script:expr; expect_within($calls:expr) $($reaction:expr),+; $($rest:tt)*) => {
+        $crate::scenario!(@ $script.expect_within($calls, [$($reaction),+]); $($rest)*)
+    };
+    (@ $script:expr; $($bad:tt)*) => {
+        compile_error!(
+            "expected `send`, `expect`, `expect_any_order`, `expect_nothing` \
+             or `expect_within(n)` followed by `;`"
+        )
+    };
+    ($($line:tt)*) => {
+        $crate::scenario!(@ $crate::script::Script::new(); $($line)*)
+    };
+}
+
+#[cfg(test)]
+mod test_script {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::{judge, judge_panic};
+    use alloc::string::ToString;
+
+    fn run(
+        script: Script<StackChange>,
+        object: impl FnMut(StackChange) -> Vec<StackChange>,
+    ) -> Judgment<StackChange, Deviation<StackChange>> {
+        judge(script, object).unwrap().judgment
+    }
+
+    #[test]
+    fn test_scenario_macro() {
+        let script = scenario! {
+            send Push(1);
+            send Push(2);
+            expect_nothing;
+            send Pop;
+            expect Value(Some(2));
+            send Pop;
+            expect Value(Some(1));
+        };
+        assert_eq!(
+            script,
+            Script::new()
+                .send(Push(1))
+                .send(Push(2))
+                .expect_nothing()
+                .send(Pop)
+                .expect([Value(Some(2))])
+                .send(Pop)
+                .expect([Value(Some(1))])
+        );
+        let mut stack = vec![];
+        assert_eq!(judge_panic(script.clone(), demo_impl_good(&mut stack)), 4);
+
+        let mut count = 0;
+        assert_eq!(
+            run(script, demo_impl_zero_smart(&mut count)),
+            Judgment::Fault(Deviation::Wrong {
+                line: 5,
+                call: 3,
+                expected: Value(Some(2)),
+                reaction: Value(Some(0)),
+            })
+        );
+    }
+
+    /// A `scenario!` of these lines, forty times over.
+    macro_rules! forty_times {
+        ($($line:tt)*) => {
+            scenario! {
+                $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)*
+                $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)*
+                $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)*
+                $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)*
+                $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)* $($line)*
+            }
+        };
+    }
+
+    #[test]
+    fn test_long_scenario() {
+        // 160 lines, with the crate's recursion limit raised (for tests only).
+        let script = forty_times! {
+            send Push(7);
+            expect_nothing;
+            send Pop;
+            expect Value(Some(7));
+        };
+        let mut stack = vec![];
+        assert_eq!(judge_panic(script, demo_impl_good(&mut stack)), 80);
+    }
+
+    #[test]
+    fn test_nothing_and_missing() {
+        let script =
+            || scenario! { send Push(1); expect_nothing; send Pop; expect Value(Some(1)); };
+        let Judgment::Fault(f) = run(script(), demo_impl_dumb()) else {
+            panic!("no fault");
+        };
+        assert_eq!(
+            f.to_string(),
+            "line 2 (call 1): expected nothing, got [Value(None)]"
+        );
+        assert_eq!(
+            run(script(), demo_impl_discard()),
+            Judgment::Fault(Deviation::Missing {
+                line: 4,
+                call: 2,
+                expected: Value(Some(1)),
+            })
+        );
+    }
+
+    /// A stack that answers every pop one call late.
+    fn demo_impl_late() -> impl FnMut(StackChange) -> Vec<StackChange> {
+        let mut stack = vec![];
+        let mut late = None;
+        move |msg| {
+            let now = match msg {
+                Push(x) => {
+                    stack.push(x);
+                    None
+                }
+                Pop => Some(Value(stack.pop())),
+                Value(_) => panic!("Value in demo_impl"),
+            };
+            core::mem::replace(&mut late, now).into_iter().collect()
+        }
+    }
+
+    #[test]
+    fn test_within() {
+        let script = |n| {
+            scenario! {
+                send Push(1);
+                send Push(2);
+                send Pop;
+                expect_within(n) Value(Some(2));
+                send Pop;
+                expect_within(n) Value(Some(1));
+                send Push(3);
+            }
+        };
+        assert_eq!(run(script(1), demo_impl_late()), Judgment::Done);
+        assert_eq!(
+            run(script(0), demo_impl_late()),
+            Judgment::Fault(Deviation::Missing {
+                line: 4,
+                call: 3,
+                expected: Value(Some(2)),
+            })
+        );
+        // The last one can't come if nothing's sent after it.
+        let script = scenario! {
+            send Push(1);
+            send Pop;
+            expect_within(5) Value(Some(1));
+        };
+        assert_eq!(
+            run(script, demo_impl_late()),
+            Judgment::Fault(Deviation::Late {
+                line: 3,
+                call: 2,
+                expected: Value(Some(1)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_any_order() {
+        let both = |msg| match msg {
+            Pop => vec![Value(Some(2)), Value(Some(1))],
+            _ => vec![],
+        };
+        let script = scenario! {
+            send Pop;
+            expect_any_order Value(Some(1)), Value(Some(2));
+        };
+        assert_eq!(run(script, both), Judgment::Done);
+        let script = scenario! {
+            send Pop;
+            expect_any_order Value(Some(1)), Value(Some(3));
+        };
+        assert_eq!(
+            run(script, both),
+            Judgment::Fault(Deviation::Wrong {
+                line: 2,
+                call: 1,
+                expected: Value(Some(3)),
+                reaction: Value(Some(2)),
+            })
+        );
+    }
+}
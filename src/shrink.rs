@@ -0,0 +1,291 @@
+//! Shrinking a failing scenario down to something a human can read.
+//!
+//! A randomized judge may find a fault after thousands of calls, most of which
+//! have nothing to do with the fault. [`minimize`] takes the failing sequence of
+//! stimuli and repeatedly re-runs the simulation on smaller and smaller parts of
+//! it (delta debugging, a.k.a. *ddmin*), until no single part can be removed
+//! without losing the fault.
+//!
+//! Because the sequence is replayed over and over, you have to provide factories:
+//! - a *judge factory* that builds a judge which sends exactly the given stimuli
+//!   (in order) and judges the reactions as usual, and
+//! - an *object factory* that builds a fresh object for every run
+//!   (or, with [`minimize_reset`], an [`Object`] that can [reset](Object::reset) itself).
+//!
+//! A run counts as "still failing" if it ends in a [`Judgment::Fault`]
+//! (with the `_by` variants, one of the same kind as the original one,
+//! such as the same enum variant, see [`same_variant`]). A run that makes the judge fail
+//! (e.g., a `Pop` without a `Push` in a stack scenario) doesn't count.
+
+use alloc::vec::Vec;
+
+use crate::object::Borrowed;
+use crate::{judge_object, Judge, Judgment, Object, Outcome};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A minimal failing scenario.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Shrunk<C, F> {
+    /// The stimuli that still provoke the fault.
+    pub stimuli: Vec<C>,
+    /// The fault they provoke.
+    pub fault: F,
+    /// Number of calls it took to get there (see [`Outcome::calls`](crate::Outcome::calls)).
+    pub calls: usize,
+    /// Number of simulations run while shrinking, including the first one.
+    pub runs: usize,
+}
+
+/// Shrink a failing stimulus sequence.
+///
+/// Any fault will do, even one unlike the original; use [`minimize_by`] to be pickier.
+///
+/// Returns `None` if the original sequence doesn't fault to begin with.
+pub fn minimize<J, O>(
+    make_judge: impl FnMut(Vec<J::Change>) -> J,
+    make_object: impl FnMut() -> O,
+    failing: Vec<J::Change>,
+) -> Option<Shrunk<J::Change, J::Fault>>
+where
+    J: Judge,
+    J::Change: Clone,
+    O: Object<J::Change>,
+{
+    minimize_by(make_judge, make_object, failing, |_, _| true)
+}
+
+/// Like [`minimize`], but decide whether two faults are of the same kind
+/// with `same_kind(original, candidate)`.
+pub fn minimize_by<J, O>(
+    mut make_judge: impl FnMut(Vec<J::Change>) -> J,
+    mut make_object: impl FnMut() -> O,
+    failing: Vec<J::Change>,
+    same_kind: impl FnMut(&J::Fault, &J::Fault) -> bool,
+) -> Option<Shrunk<J::Change, J::Fault>>
+where
+    J: Judge,
+    J::Change: Clone,
+    O: Object<J::Change>,
+{
+    ddmin(
+        |stimuli| fault(judge_object(make_judge(stimuli.to_vec()), make_object())),
+        failing,
+        same_kind,
+    )
+}
+
+/// Like [`minimize`], but with the same object for every run,
+/// [reset](Object::reset) before each one, instead of a fresh one.
+pub fn minimize_reset<J, O>(
+    make_judge: impl FnMut(Vec<J::Change>) -> J,
+    object: &mut O,
+    failing: Vec<J::Change>,
+) -> Option<Shrunk<J::Change, J::Fault>>
+where
+    J: Judge,
+    J::Change: Clone,
+    O: Object<J::Change> + ?Sized,
+{
+    minimize_reset_by(make_judge, object, failing, |_, _| true)
+}
+
+/// Like [`minimize_reset`], but decide whether two faults are of the same kind
+/// with `same_kind(original, candidate)` (as in [`minimize_by`]).
+pub fn minimize_reset_by<J, O>(
+    mut make_judge: impl FnMut(Vec<J::Change>) -> J,
+    object: &mut O,
+    failing: Vec<J::Change>,
+    same_kind: impl FnMut(&J::Fault, &J::Fault) -> bool,
+) -> Option<Shrunk<J::Change, J::Fault>>
+where
+    J: Judge,
+    J::Change: Clone,
+    O: Object<J::Change> + ?Sized,
+{
+    let run = |stimuli: &[J::Change]| {
+        object.reset();
+        fault(judge_object(
+            make_judge(stimuli.to_vec()),
+            Borrowed(&mut *object),
+        ))
+    };
+    ddmin(run, failing, same_kind)
+}
+
+/// Whether two faults are the same variant of an enum, for [`minimize_by`]
+/// and [`minimize_reset_by`].
+///
+/// Only meaningful if `F` is an enum: for any other type, the result is unspecified
+/// (see [`core::mem::discriminant`]).
+pub fn same_variant<F>(a: &F, b: &F) -> bool {
+    core::mem::discriminant(a) == core::mem::discriminant(b)
+}
+
+/// The fault and the call count, if the run faulted.
+fn fault<J: Judge>(result: Result<Outcome<J>, J::Error>) -> Option<(J::Fault, usize)> {
+    let o = result.ok()?;
+    match o.judgment {
+        Judgment::Fault(f) => Some((f, o.calls)),
+        _ => None,
+    }
+}
+
+/// Delta debugging, with `attempt` to run a scenario (see [`fault`]).
+fn ddmin<C: Clone, F>(
+    mut attempt: impl FnMut(&[C]) -> Option<(F, usize)>,
+    failing: Vec<C>,
+    mut same_kind: impl FnMut(&F, &F) -> bool,
+) -> Option<Shrunk<C, F>> {
+    let mut runs = 0;
+    let mut run = |stimuli: &[C]| {
+        runs += 1;
+        attempt(stimuli)
+    };
+
+    let (original, calls) = run(&failing)?;
+    let mut best = Shrunk {
+        stimuli: failing,
+        fault: original,
+        calls,
+        runs: 0,
+    };
+    // Nothing after the fault matters.
+    best.stimuli.truncate(calls);
+
+    let mut n = 2;
+    while best.stimuli.len() >= 2 {
+        let len = best.stimuli.len();
+        let chunk = len.div_ceil(n);
+        let parts = (0..len).step_by(chunk).map(|i| i..(i + chunk).min(len));
+
+        // Try each part on its own, then each complement.
+        let mut found = None;
+        for r in parts.clone() {
+            let sub = &best.stimuli[r];
+            if let Some((f, c)) = run(sub) {
+                if same_kind(&best.fault, &f) {
+                    found = Some((sub.to_vec(), f, c, 2));
+                    break;
+                }
+            }
+        }
+        if found.is_none() {
+            for r in parts {
+                let mut sub = best.stimuli[..r.start].to_vec();
+                sub.extend_from_slice(&best.stimuli[r.end..]);
+                if let Some((f, c)) = run(&sub) {
+                    if same_kind(&best.fault, &f) {
+                        found = Some((sub, f, c, (n - 1).max(2)));
+                        break;
+                    }
+                }
+            }
+        }
+
+        match found {
+            Some((mut stimuli, fault, calls, next_n)) => {
+                stimuli.truncate(calls);
+                best.stimuli = stimuli;
+                best.fault = fault;
+                best.calls = calls;
+                n = next_n;
+            }
+            None if n >= len => break,
+            None => n = (2 * n).min(len),
+        }
+    }
+
+    best.runs = runs;
+    Some(best)
+}
+
+#[cfg(test)]
+mod test_shrink {
+    use super::*;
+    use crate::test_stack::*;
+
+    #[test]
+    fn test_minimize_irrelevant() {
+        let failing = {
+            #[rustfmt::skip]
+            let sce = vec![
+                Push(1), Push(2), Push(3),
+                Pop, Pop, Pop,
+                Push(4), Push(5),
+                Pop, Pop,
+            ];
+            sce
+        };
+        let s = minimize(StackJudge::new_scenario, demo_impl_irrelevant, failing).unwrap();
+        // Can't pop from an empty stack, so a push must come first.
+        assert!(matches!(s.stimuli[..], [Push(_), Pop]), "{:?}", s.stimuli);
+        assert_eq!(s.fault, "undefined response from stack");
+        assert_eq!(s.calls, 2);
+        assert!(s.runs > 1);
+    }
+
+    #[test]
+    fn test_minimize_not_failing() {
+        let s = minimize(StackJudge::new_scenario, demo_impl_lazy, vec![Push(1), Pop]);
+        assert_eq!(s, None);
+    }
+
+    #[test]
+    fn test_minimize_by_kind() {
+        // Only accept the "expected X, got Y" kind of fault, and only for X = 2.
+        let failing = vec![Push(1), Push(2), Pop, Pop];
+        let s = minimize_by(
+            StackJudge::new_scenario,
+            || {
+                let mut count = 0;
+                move |msg| demo_impl_zero_smart(&mut count)(msg)
+            },
+            failing,
+            |_, f| f == "expected 2, got 0",
+        )
+        .unwrap();
+        assert_eq!(s.stimuli, vec![Push(2), Pop]);
+    }
+
+    #[test]
+    fn test_same_variant() {
+        assert!(same_variant(&Some(1), &Some(2)));
+        assert!(!same_variant(&Some(1), &None));
+    }
+
+    /// A stack that pops 2 as 0, and counts its resets.
+    #[derive(Default)]
+    struct Zeroes {
+        stack: Vec<i32>,
+        resets: usize,
+    }
+    impl Object<StackChange> for Zeroes {
+        fn react(&mut self, msg: StackChange) -> Vec<StackChange> {
+            match msg {
+                Push(x) => {
+                    self.stack.push(x);
+                    vec![]
+                }
+                Pop => vec![Value(self.stack.pop().map(|x| if x == 2 { 0 } else { x }))],
+                Value(_) => panic!("Value in demo_impl"),
+            }
+        }
+        fn reset(&mut self) {
+            self.stack.clear();
+            self.resets += 1;
+        }
+    }
+
+    #[test]
+    fn test_minimize_reset() {
+        let mut object = Zeroes::default();
+        let failing = vec![Push(1), Push(2), Push(3), Pop, Pop, Pop];
+        let s = minimize_reset(StackJudge::new_scenario, &mut object, failing).unwrap();
+        assert_eq!(s.stimuli, vec![Push(2), Pop]);
+        assert_eq!(s.fault, "expected 2, got 0");
+        assert_eq!(object.resets, s.runs);
+    }
+}
@@ -0,0 +1,378 @@
+//! Running a simulation one step at a time.
+//!
+//! [`judge`](crate::judge) runs the whole simulation in one go. A [`Simulation`]
+//! does the same thing, but lets you stop after every call of the object, so
+//! you can drive it from a debugger, a UI, or another test, and look at
+//! the judge and the object in between.
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::mem;
+
+use crate::time::Clock;
+use crate::trace::{Note, Step};
+use crate::{Config, Exit, Judge, Judgment, Limit, Object, Outcome, StepContext};
+
+/// A simulation in progress.
+///
+/// It owns the judge and the object. Each [`step`](Simulation::step) asks
+/// the judge for its next move, and, if the judge wants to continue,
+/// delivers the stimulus to the object and collects its reactions
+/// (which are judged on the next step).
+///
+/// ```
+/// use caet::{Judge, Judgment, Simulation};
+/// use std::convert::Infallible;
+///
+/// /// Count to three.
+/// struct Count(u32);
+/// impl Judge for Count {
+///     type Change = u32;
+///     type Fault = String;
+///     type Error = Infallible;
+///     fn next(&mut self, reactions: Vec<u32>) -> Result<Judgment<u32, String>, Infallible> {
+///         if reactions.iter().any(|&r| r != self.0) {
+///             return Ok(Judgment::Fault(format!("not an echo: {reactions:?}")));
+///         }
+///         self.0 += 1;
+///         Ok(if self.0 <= 3 { Judgment::Continue(self.0) } else { Judgment::Done })
+///     }
+/// }
+///
+/// let mut sim = Simulation::new(Count(0), |x| vec![x]);
+/// let step = sim.step().unwrap().unwrap();
+/// assert_eq!((step.stimulus, step.reactions), (1, vec![1]));
+/// assert_eq!(sim.judge().0, 1);
+///
+/// let outcome = sim.run_to_end().unwrap().outcome().unwrap();
+/// assert_eq!(outcome.judgment, Judgment::Done);
+/// assert_eq!(outcome.calls, 3);
+/// ```
+pub struct Simulation<J: Judge, O, E = Infallible> {
+    judge: J,
+    object: O,
+    config: Config,
+    /// Reactions not yet judged.
+    pending: Vec<J::Change>,
+    /// Number of calls of the object so far.
+    calls: usize,
+    /// Number of reactions produced so far.
+    reactions: usize,
+    /// How the simulation ended, once it has.
+    exit: Option<Exit<J, E>>,
+    /// Lent to the judge on every turn.
+    cx: StepContext,
+    /// The virtual clock, for timed runs (see [`judge_timed`](crate::time::judge_timed));
+    /// otherwise, nothing is ever scheduled on it, and it stays at zero.
+    clock: Clock<J::Change>,
+}
+
+impl<J, O> Simulation<J, O>
+where
+    J: Judge,
+    O: Object<J::Change>,
+{
+    /// Set up a simulation without limits.
+    pub fn new(judge: J, object: O) -> Self {
+        Self::with_config(Config::new(), judge, object)
+    }
+
+    /// Set up a simulation within the limits of a [`Config`].
+    pub fn with_config(config: Config, judge: J, object: O) -> Self {
+        Self::build(config, judge, object)
+    }
+
+    /// Let the judge make its next move, and, if it's a stimulus, deliver it.
+    ///
+    /// Returns the stimulus and the object's reactions, or `None` if the
+    /// simulation is over (see [`exit`](Simulation::exit)).
+    ///
+    /// If the judge fails, its error is returned and the simulation stays where it was,
+    /// except that the reactions the judge was given are gone.
+    pub fn step(&mut self) -> Result<Option<Step<J::Change>>, J::Error>
+    where
+        J::Change: Clone,
+    {
+        Ok(self.advance(J::Change::clone)?.map(|stimulus| Step {
+            stimulus,
+            reactions: self.pending.clone(),
+            causes: Vec::new(),
+        }))
+    }
+
+    /// Step until the simulation is over, and say how it ended.
+    pub fn run_to_end(&mut self) -> Result<&Exit<J>, J::Error> {
+        while self.advance(|_| ())?.is_some() {}
+        Ok(self.exit.as_ref().expect("over"))
+    }
+
+    /// One step. `keep` gets to look at the stimulus before the object takes it.
+    pub(crate) fn advance<S>(
+        &mut self,
+        keep: impl FnOnce(&J::Change) -> S,
+    ) -> Result<Option<S>, J::Error> {
+        self.advance_by(keep, |object, msg| Ok(object.react(msg)))
+    }
+}
+
+/// For runners whose objects aren't plain [`Object`]s (or may crash with an `E`):
+/// they say how to deliver a stimulus, and the simulation does the rest.
+impl<J: Judge, O, E> Simulation<J, O, E> {
+    pub(crate) fn build(config: Config, judge: J, object: O) -> Self {
+        Self {
+            cx: StepContext::new(config.seed),
+            judge,
+            object,
+            config,
+            pending: Vec::new(),
+            calls: 0,
+            reactions: 0,
+            exit: None,
+            clock: Clock::new(),
+        }
+    }
+
+    /// One step. `keep` gets to look at the stimulus before `react` delivers it;
+    /// if `react` fails, the object has crashed.
+    pub(crate) fn advance_by<S>(
+        &mut self,
+        keep: impl FnOnce(&J::Change) -> S,
+        react: impl FnOnce(&mut O, J::Change) -> Result<Vec<J::Change>, E>,
+    ) -> Result<Option<S>, J::Error> {
+        self.advance_on(
+            |judge, reactions, cx, _| judge.next_with(reactions, cx),
+            keep,
+            |object, msg, _| react(object, msg),
+        )
+    }
+
+    /// One step on the clock, for judges and objects that can see it:
+    /// `next` asks the judge, and when it has nothing to send right away,
+    /// the clock moves on to the next scheduled stimulus.
+    pub(crate) fn advance_on<S>(
+        &mut self,
+        next: impl FnOnce(
+            &mut J,
+            Vec<J::Change>,
+            &mut StepContext,
+            &mut Clock<J::Change>,
+        ) -> Result<Judgment<J::Change, J::Fault>, J::Error>,
+        keep: impl FnOnce(&J::Change) -> S,
+        react: impl FnOnce(&mut O, J::Change, &mut Clock<J::Change>) -> Result<Vec<J::Change>, E>,
+    ) -> Result<Option<S>, J::Error> {
+        if self.exit.is_some() {
+            return Ok(None);
+        }
+        let pending = mem::take(&mut self.pending);
+        let msg = match next(&mut self.judge, pending, &mut self.cx, &mut self.clock)? {
+            Judgment::Continue(msg) => msg,
+            Judgment::Done if self.clock.pending() > 0 => {
+                if (self.config.time).is_some_and(|max| self.clock.next_time() > Some(max)) {
+                    self.exhaust(Limit::Time);
+                    return Ok(None);
+                }
+                self.clock.advance().expect("pending")
+            }
+            j => {
+                self.end(Exit::Judged(Outcome {
+                    judgment: j,
+                    calls: self.calls,
+                    seed: self.cx.seed(),
+                    time: self.clock.now(),
+                }));
+                return Ok(None);
+            }
+        };
+        if self.config.calls.is_some_and(|max| self.calls >= max) {
+            self.exhaust(Limit::Calls);
+            return Ok(None);
+        }
+        let kept = keep(&msg);
+        let reactions = react(&mut self.object, msg, &mut self.clock);
+        self.calls += 1;
+        match reactions {
+            Ok(reactions) => self.pending = reactions,
+            Err(error) => {
+                self.end(Exit::Crashed {
+                    call: self.calls,
+                    error,
+                });
+                return Ok(Some(kept));
+            }
+        }
+        self.reactions += self.pending.len();
+        self.cx.called(self.pending.len());
+        if self
+            .config
+            .reactions
+            .is_some_and(|max| self.reactions > max)
+        {
+            // The step did happen; the next one won't.
+            self.exhaust(Limit::Reactions);
+        }
+        Ok(Some(kept))
+    }
+
+    /// End the simulation here, for a reason the simulation itself can't see
+    /// (say, a panic).
+    pub(crate) fn end(&mut self, exit: Exit<J, E>) {
+        self.exit = Some(exit);
+    }
+
+    fn exhaust(&mut self, limit: Limit) {
+        self.end(Exit::Exhausted {
+            limit,
+            calls: self.calls,
+            reactions: self.reactions,
+            time: self.clock.now(),
+        });
+    }
+}
+
+impl<J: Judge, O, E> Simulation<J, O, E> {
+    /// The judge.
+    pub fn judge(&self) -> &J {
+        &self.judge
+    }
+
+    /// The judge, mutably. Handle with care.
+    pub fn judge_mut(&mut self) -> &mut J {
+        &mut self.judge
+    }
+
+    /// The object.
+    pub fn object(&self) -> &O {
+        &self.object
+    }
+
+    /// The object, mutably. Handle with care.
+    pub fn object_mut(&mut self) -> &mut O {
+        &mut self.object
+    }
+
+    /// The limits the simulation runs within.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The context lent to the judge on every turn.
+    pub fn context(&self) -> &StepContext {
+        &self.cx
+    }
+
+    /// The virtual clock, which only moves in timed simulations
+    /// (see [`timed`](Simulation::timed)).
+    pub fn clock(&self) -> &Clock<J::Change> {
+        &self.clock
+    }
+
+    /// Keep the judge's annotations (see [`StepContext::annotate`]),
+    /// until they're taken with [`take_notes`](Simulation::take_notes).
+    /// By default, they're dropped.
+    pub fn keep_notes(&mut self, keep: bool) {
+        self.cx.keep_notes(keep);
+    }
+
+    /// Take the annotations kept so far.
+    pub fn take_notes(&mut self) -> Vec<Note> {
+        self.cx.take_notes()
+    }
+
+    /// Number of calls of the object so far.
+    pub fn calls(&self) -> usize {
+        self.calls
+    }
+
+    /// Number of reactions produced so far.
+    pub fn reactions(&self) -> usize {
+        self.reactions
+    }
+
+    /// The object's latest reactions, which the judge will see on the next step.
+    pub fn pending(&self) -> &[J::Change] {
+        &self.pending
+    }
+
+    /// Whether the simulation is over.
+    pub fn is_over(&self) -> bool {
+        self.exit.is_some()
+    }
+
+    /// How the simulation ended, if it has.
+    pub fn exit(&self) -> Option<&Exit<J, E>> {
+        self.exit.as_ref()
+    }
+
+    /// How the simulation ended, if it has.
+    pub fn into_exit(self) -> Option<Exit<J, E>> {
+        self.exit
+    }
+
+    /// Take the simulation apart: the judge, the object, and how it ended, if it has.
+    pub fn into_parts(self) -> (J, O, Option<Exit<J, E>>) {
+        (self.judge, self.object, self.exit)
+    }
+}
+
+#[cfg(test)]
+mod test_simulation {
+    use super::*;
+    use crate::test_stack::*;
+
+    #[test]
+    fn test_step_by_step() {
+        let mut stack = vec![];
+        let mut sim = Simulation::new(scenario_1(), demo_impl_good(&mut stack));
+        for _ in 0..3 {
+            let step = sim.step().unwrap().unwrap();
+            assert_eq!(step.reactions, vec![]);
+        }
+        assert_eq!(sim.judge().ref_impl, vec![1, 2, 3]);
+        let step = sim.step().unwrap().unwrap();
+        assert_eq!(step.stimulus, Pop);
+        assert_eq!(step.reactions, vec![Value(Some(3))]);
+        assert_eq!(sim.pending(), &[Value(Some(3))]);
+        // Not judged yet.
+        assert_eq!(sim.judge().expect, [3]);
+        assert_eq!(sim.calls(), 4);
+        assert!(!sim.is_over());
+
+        let exit = sim.run_to_end().unwrap();
+        assert_eq!(
+            exit,
+            &Exit::Judged(Outcome {
+                judgment: Judgment::Done,
+                calls: 7,
+                seed: 0,
+                time: 0,
+            })
+        );
+        assert!(sim.is_over());
+        assert_eq!(sim.step(), Ok(None));
+    }
+
+    #[test]
+    fn test_step_fault() {
+        let mut sim = Simulation::new(scenario_1(), demo_impl_dumb());
+        assert!(sim.step().unwrap().is_some());
+        assert_eq!(sim.step().unwrap(), None);
+        let o = sim.into_exit().unwrap().judged().unwrap();
+        assert_eq!(
+            o.judgment,
+            Judgment::Fault("too many reactions".to_string())
+        );
+    }
+
+    #[test]
+    fn test_step_error() {
+        let mut stack = vec![];
+        let mut sim = Simulation::new(scenario_2(), demo_impl_good(&mut stack));
+        assert!(sim.step().unwrap().is_some());
+        assert!(sim.step().unwrap().is_some());
+        assert_eq!(
+            sim.step(),
+            Err("bad sim: more pops than pushes".to_string())
+        );
+        assert!(!sim.is_over());
+    }
+}
@@ -0,0 +1,382 @@
+//! Virtual time.
+//!
+//! Without a clock, the only notion of time is the order of events:
+//! this call came after that one. That's not enough to model timeouts,
+//! retries, or rate limits. [`judge_timed`] runs the simulation on a virtual
+//! [`Clock`], discrete-event style:
+//! - The judge ([`TimedJudge`]) may send a stimulus right away, as usual,
+//!   or schedule it for later, on the clock.
+//! - The object ([`TimedObject`]) may set a timer, which is also a stimulus
+//!   scheduled on the clock: the observation it wants to make when the timer goes off.
+//! - Whenever the judge has nothing more to send right away, the clock jumps
+//!   to the next scheduled stimulus, and delivers it.
+//!
+//! Scheduled stimuli can be cancelled or rescheduled; [`des`](crate::des) has
+//! the event queue underneath, and arrival processes to fill it with.
+//!
+//! Nothing happens between scheduled stimuli, so a simulation that spans hours
+//! of virtual time takes no longer to run than one that spans milliseconds.
+//! The unit of time is up to you.
+//!
+//! (By the doctrine of the passivity of observations, an object can't arrange
+//! for a signal to arrive at a certain time. A timer is the exception that proves
+//! the rule: it's a piece of the universe that the object merely asks to ring.)
+
+use alloc::vec::Vec;
+
+use crate::des::{Agenda, EventId};
+use crate::trace::Step;
+use crate::{Config, Exit, Judge, Judgment, Object, Simulation, StepContext};
+
+/// A point in virtual time, in whatever unit you like.
+pub type Time = u64;
+
+/// The virtual clock, and the stimuli scheduled on it.
+///
+/// Stimuli scheduled for the same time are delivered in the order they were scheduled.
+/// Each one gets an [`EventId`], to [`cancel`](Self::cancel) or
+/// [`reschedule`](Self::reschedule) it with; see [`des`](crate::des) for more.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Clock<C> {
+    now: Time,
+    /// Scheduled stimuli.
+    agenda: Agenda<C>,
+}
+
+impl<C> Default for Clock<C> {
+    fn default() -> Self {
+        Self {
+            now: 0,
+            agenda: Agenda::new(),
+        }
+    }
+}
+
+impl<C> Clock<C> {
+    /// A clock at time zero, with nothing scheduled.
+    ///
+    /// [`judge_timed`] makes its own; this is for calling a judge or an object by hand.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The current time.
+    pub fn now(&self) -> Time {
+        self.now
+    }
+
+    /// Deliver this stimulus to the object at this time
+    /// (or, if it's in the past, as soon as possible).
+    pub fn schedule(&mut self, at: Time, stimulus: C) -> EventId {
+        self.agenda.schedule(at.max(self.now), stimulus)
+    }
+
+    /// Deliver this stimulus to the object after this much time.
+    pub fn schedule_in(&mut self, delay: Time, stimulus: C) -> EventId {
+        self.schedule(self.now.saturating_add(delay), stimulus)
+    }
+
+    /// Don't deliver this stimulus after all.
+    ///
+    /// Returns it, or `None` if it's already been delivered (or cancelled).
+    pub fn cancel(&mut self, id: EventId) -> Option<C> {
+        self.agenda.cancel(id)
+    }
+
+    /// Deliver this stimulus at another time (or, if it's in the past, as soon as possible).
+    ///
+    /// Returns `false` if it's already been delivered (or cancelled).
+    pub fn reschedule(&mut self, id: EventId, at: Time) -> bool {
+        self.agenda.reschedule(id, at.max(self.now))
+    }
+
+    /// The stimuli scheduled and not yet delivered.
+    pub fn agenda(&self) -> &Agenda<C> {
+        &self.agenda
+    }
+
+    /// Number of stimuli scheduled and not yet delivered.
+    pub fn pending(&self) -> usize {
+        self.agenda.len()
+    }
+
+    /// When the next scheduled stimulus is due, if there is one.
+    pub fn next_time(&self) -> Option<Time> {
+        self.agenda.peek().map(|(at, _, _)| at)
+    }
+
+    /// Forget every scheduled stimulus.
+    pub fn clear(&mut self) {
+        self.agenda.clear();
+    }
+
+    /// Move on to the next scheduled stimulus, and take it.
+    pub(crate) fn advance(&mut self) -> Option<C> {
+        let (at, _, stimulus) = self.agenda.pop()?;
+        self.now = at;
+        Some(stimulus)
+    }
+}
+
+/// A judge that knows about the [`Clock`].
+///
+/// See [`judge_timed`].
+pub trait TimedJudge: Judge {
+    /// Like [`Judge::next_with`], but with the clock.
+    ///
+    /// [`Continue`](Judgment::Continue) sends a stimulus right away.
+    /// [`Done`](Judgment::Done) means there's nothing to send right away;
+    /// the simulation moves on to the next scheduled stimulus, and
+    /// only ends when there's none left.
+    /// [`Fault`](Judgment::Fault) ends the simulation, as usual.
+    fn next_timed(
+        &mut self,
+        reactions: Vec<Self::Change>,
+        cx: &mut StepContext,
+        clock: &mut Clock<Self::Change>,
+    ) -> Result<Judgment<Self::Change, Self::Fault>, Self::Error>;
+}
+
+/// An object that knows about the [`Clock`], and may set timers on it.
+///
+/// It's implemented for every `FnMut(C, &mut Clock<C>) -> Vec<C>` closure.
+/// For an object that doesn't care about time, see [`Untimed`].
+pub trait TimedObject<C> {
+    /// Like [`Object::react`], but with the clock.
+    fn react_timed(&mut self, observation: C, clock: &mut Clock<C>) -> Vec<C>;
+}
+
+impl<C, F> TimedObject<C> for F
+where
+    F: FnMut(C, &mut Clock<C>) -> Vec<C>,
+{
+    fn react_timed(&mut self, observation: C, clock: &mut Clock<C>) -> Vec<C> {
+        self(observation, clock)
+    }
+}
+
+/// An [`Object`] that doesn't care about time.
+pub struct Untimed<O>(pub O);
+
+impl<C, O: Object<C>> TimedObject<C> for Untimed<O> {
+    fn react_timed(&mut self, observation: C, _: &mut Clock<C>) -> Vec<C> {
+        self.0.react(observation)
+    }
+}
+
+/// Run the simulation on a virtual clock, within the limits of a [`Config`], and with its seed.
+///
+/// See the module documentation. The simulation stops with [`Exit::Exhausted`]
+/// (with [`Limit::Time`](crate::Limit::Time)) before the clock would pass [`Config::time`];
+/// otherwise, the final time is in [`Outcome::time`](crate::Outcome::time).
+/// To go one step at a time, see [`Simulation::timed`].
+pub fn judge_timed<J, O>(config: Config, judge: J, object: O) -> Result<Exit<J>, J::Error>
+where
+    J: TimedJudge,
+    O: TimedObject<J::Change>,
+{
+    let mut sim = Simulation::timed(config, judge, object);
+    while sim.advance_timed(|_| ())?.is_some() {}
+    Ok(sim.into_exit().expect("over"))
+}
+
+impl<J, O> Simulation<J, O>
+where
+    J: TimedJudge,
+    O: TimedObject<J::Change>,
+{
+    /// Set up a simulation on a virtual clock, within the limits of a [`Config`],
+    /// to step through like any other (see [`judge_timed`]).
+    pub fn timed(config: Config, judge: J, object: O) -> Self {
+        Self::build(config, judge, object)
+    }
+
+    /// Like [`step`](Simulation::step), but on the clock: once the judge has nothing
+    /// to send right away, the stimulus is the next one scheduled
+    /// (see [`clock`](Simulation::clock) for the time).
+    pub fn step_timed(&mut self) -> Result<Option<Step<J::Change>>, J::Error>
+    where
+        J::Change: Clone,
+    {
+        Ok(self.advance_timed(J::Change::clone)?.map(|stimulus| Step {
+            stimulus,
+            reactions: self.pending().to_vec(),
+            causes: Vec::new(),
+        }))
+    }
+
+    /// One step. `keep` gets to look at the stimulus before the object takes it.
+    fn advance_timed<S>(
+        &mut self,
+        keep: impl FnOnce(&J::Change) -> S,
+    ) -> Result<Option<S>, J::Error> {
+        self.advance_on(
+            |judge, reactions, cx, clock| judge.next_timed(reactions, cx, clock),
+            keep,
+            |object, msg, clock| Ok(object.react_timed(msg, clock)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test_time {
+    use super::*;
+    use crate::Limit;
+    use alloc::format;
+    use alloc::string::String;
+    use core::convert::Infallible;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Net {
+        /// Judge: start sending.
+        Start,
+        /// Client: here's attempt number n.
+        Request(u32),
+        /// Server: got it.
+        Ack,
+        /// Client's timer: attempt n may have been lost.
+        Timeout(u32),
+    }
+    use Net::*;
+
+    /// A client that retries after 10 ticks without an acknowledgement.
+    fn client() -> impl FnMut(Net, &mut Clock<Net>) -> Vec<Net> {
+        let mut attempt = 0;
+        let mut acked = false;
+        move |msg, clock| match msg {
+            Start | Timeout(_) if !acked => {
+                attempt += 1;
+                clock.schedule_in(10, Timeout(attempt));
+                vec![Request(attempt)]
+            }
+            Ack => {
+                acked = true;
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// A server behind a link that loses the first `lose` requests,
+    /// and takes 3 ticks to acknowledge the others.
+    #[derive(Debug, PartialEq)]
+    struct Lossy {
+        lose: u32,
+        begun: bool,
+    }
+    impl Judge for Lossy {
+        type Change = Net;
+        type Fault = String;
+        type Error = Infallible;
+        fn next(&mut self, reactions: Vec<Net>) -> Result<Judgment<Net, String>, Infallible> {
+            self.next_timed(reactions, &mut StepContext::new(0), &mut Clock::new())
+        }
+    }
+    impl TimedJudge for Lossy {
+        fn next_timed(
+            &mut self,
+            reactions: Vec<Net>,
+            _: &mut StepContext,
+            clock: &mut Clock<Net>,
+        ) -> Result<Judgment<Net, String>, Infallible> {
+            if !self.begun {
+                self.begun = true;
+                return Ok(Judgment::Continue(Start));
+            }
+            for r in reactions {
+                let Request(n) = r else {
+                    return Ok(Judgment::Fault(format!("not a request: {r:?}")));
+                };
+                let due = 10 * (n as Time - 1);
+                if clock.now() != due {
+                    return Ok(Judgment::Fault(format!(
+                        "attempt {n} at {}, not {due}",
+                        clock.now()
+                    )));
+                }
+                if n > self.lose {
+                    clock.schedule_in(3, Ack);
+                }
+            }
+            Ok(Judgment::Done)
+        }
+    }
+
+    #[test]
+    fn test_retry() {
+        let exit = judge_timed(
+            Config::new(),
+            Lossy {
+                lose: 2,
+                begun: false,
+            },
+            client(),
+        )
+        .unwrap();
+        let o = exit.judged().unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+        // Start, two timeouts, the ack at 23, and the last timer going off at 30.
+        assert_eq!((o.calls, o.time), (5, 30));
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let exit = judge_timed(
+            Config::new().max_time(25),
+            Lossy {
+                lose: 2,
+                begun: false,
+            },
+            client(),
+        )
+        .unwrap();
+        assert_eq!(
+            exit,
+            Exit::Exhausted {
+                limit: Limit::Time,
+                calls: 4,
+                reactions: 3,
+                // The ack came at 23, and the timer at 30 would be too late.
+                time: 23,
+            }
+        );
+    }
+
+    #[test]
+    fn test_step_timed() {
+        let lossy = Lossy {
+            lose: 2,
+            begun: false,
+        };
+        let mut sim = Simulation::timed(Config::new(), lossy, client());
+        let step = sim.step_timed().unwrap().unwrap();
+        assert_eq!((step.stimulus, step.reactions), (Start, vec![Request(1)]));
+        assert_eq!((sim.clock().now(), sim.clock().pending()), (0, 1));
+        // Nothing more to send right away, so the timer goes off.
+        let step = sim.step_timed().unwrap().unwrap();
+        assert_eq!(
+            (step.stimulus, step.reactions),
+            (Timeout(1), vec![Request(2)])
+        );
+        assert_eq!(sim.clock().now(), 10);
+        while sim.step_timed().unwrap().is_some() {}
+        assert_eq!(sim.exit().unwrap().calls(), 5);
+        assert_eq!(sim.clock().now(), 30);
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut clock = Clock::new();
+        clock.schedule(5, 'b');
+        clock.schedule(3, 'a');
+        clock.schedule(5, 'c');
+        assert_eq!((clock.pending(), clock.next_time()), (3, Some(3)));
+        assert_eq!(clock.advance(), Some('a'));
+        // The past is now.
+        clock.schedule(1, 'z');
+        assert_eq!(clock.next_time(), Some(3));
+        let rest: Vec<_> = core::iter::from_fn(|| clock.advance()).collect();
+        assert_eq!(rest, ['z', 'b', 'c']);
+        assert_eq!(clock.now(), 5);
+    }
+}
@@ -0,0 +1,361 @@
+//! Recording what happened during a simulation.
+//!
+//! [`judge`](crate::judge) only tells you how the simulation ended. When the
+//! object faults after a few thousand calls, that's not much to go on.
+//! [`judge_traced`] runs the same simulation, but also keeps a [`Transcript`]
+//! of every stimulus the judge sent and every reaction the object produced,
+//! along with any [`Note`]s the judge wrote down on the way.
+//! [`judge_traced_with`] does the same within the limits of a [`Config`],
+//! and keeps the transcript even if the simulation runs out of budget.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt::{self, Debug};
+
+use crate::causal::StimulusId;
+use crate::{Config, Exit, Judge, Object, Outcome, Simulation};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A single call of the object.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Step<C> {
+    /// The observation the judge sent to the object
+    /// (the payload of [`Judgment::Continue`](crate::Judgment::Continue)).
+    pub stimulus: C,
+    /// The reactions the object produced in response, in order.
+    pub reactions: Vec<C>,
+    /// For each reaction, the stimuli that caused it, if the run kept track
+    /// (see [`causal`](crate::causal)); empty otherwise.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub causes: Vec<Vec<StimulusId>>,
+}
+
+/// An annotation the judge made during a simulation.
+///
+/// See [`StepContext::annotate`](crate::StepContext::annotate).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Note {
+    /// Number of calls of the object before the note was made,
+    /// i.e., the index of the step it comes before.
+    pub call: usize,
+    /// What the judge had to say.
+    pub text: String,
+}
+
+/// An ordered record of every call of the object.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Transcript<C> {
+    /// The calls, in the order they were made.
+    pub steps: Vec<Step<C>>,
+    /// The judge's annotations, in the order they were made.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub notes: Vec<Note>,
+}
+
+impl<C> Default for Transcript<C> {
+    fn default() -> Self {
+        Self {
+            steps: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+}
+
+impl<C> Transcript<C> {
+    /// An empty transcript.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a call to the transcript.
+    pub fn push(&mut self, stimulus: C, reactions: Vec<C>) {
+        self.steps.push(Step {
+            stimulus,
+            reactions,
+            causes: Vec::new(),
+        });
+    }
+
+    /// Number of recorded calls.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Whether no call has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// The stimuli, in order.
+    pub fn stimuli(&self) -> impl Iterator<Item = &C> + '_ {
+        self.steps.iter().map(|s| &s.stimulus)
+    }
+
+    /// The notes made right before the step at this index
+    /// (or, if it's the number of steps, after the last one).
+    pub fn notes_at(&self, call: usize) -> impl Iterator<Item = &str> + '_ {
+        self.notes
+            .iter()
+            .filter(move |n| n.call == call)
+            .map(|n| n.text.as_str())
+    }
+}
+
+impl<C> From<Vec<Step<C>>> for Transcript<C> {
+    fn from(steps: Vec<Step<C>>) -> Self {
+        Self {
+            steps,
+            notes: Vec::new(),
+        }
+    }
+}
+
+/// An [`Outcome`] together with the [`Transcript`] that led to it.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(bound(
+        serialize = "J::Change: Serialize, J::Fault: Serialize",
+        deserialize = "J::Change: Deserialize<'de>, J::Fault: Deserialize<'de>"
+    ))
+)]
+pub struct Traced<J: Judge> {
+    /// How the simulation ended.
+    pub outcome: Outcome<J>,
+    /// Everything the object observed and produced on the way there.
+    pub transcript: Transcript<J::Change>,
+}
+
+// (`derive` can't see through `Outcome<J>` to bound `J::Fault`, so spell these out.)
+impl<J: Judge> Debug for Traced<J>
+where
+    Outcome<J>: Debug,
+    J::Change: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Traced")
+            .field("outcome", &self.outcome)
+            .field("transcript", &self.transcript)
+            .finish()
+    }
+}
+impl<J: Judge> Clone for Traced<J>
+where
+    Outcome<J>: Clone,
+    J::Change: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            outcome: self.outcome.clone(),
+            transcript: self.transcript.clone(),
+        }
+    }
+}
+impl<J: Judge> PartialEq for Traced<J>
+where
+    Outcome<J>: PartialEq,
+    J::Change: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.outcome == other.outcome && self.transcript == other.transcript
+    }
+}
+impl<J: Judge> Eq for Traced<J>
+where
+    Outcome<J>: Eq,
+    J::Change: Eq,
+{
+}
+
+/// How a simulation ended, however it ended, together with the [`Transcript`] that led there.
+///
+/// Like [`Traced`], for runners that return an [`Exit`] rather than an [`Outcome`].
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(bound(
+        serialize = "J::Change: Serialize, J::Fault: Serialize, E: Serialize",
+        deserialize = "J::Change: Deserialize<'de>, J::Fault: Deserialize<'de>, E: Deserialize<'de>"
+    ))
+)]
+pub struct Recorded<J: Judge, E = Infallible> {
+    /// How the simulation ended.
+    pub exit: Exit<J, E>,
+    /// Everything the object observed and produced on the way there.
+    pub transcript: Transcript<J::Change>,
+}
+
+// (See `Traced` for why these are spelled out.)
+impl<J: Judge, E> Debug for Recorded<J, E>
+where
+    Exit<J, E>: Debug,
+    J::Change: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorded")
+            .field("exit", &self.exit)
+            .field("transcript", &self.transcript)
+            .finish()
+    }
+}
+impl<J: Judge, E> Clone for Recorded<J, E>
+where
+    Exit<J, E>: Clone,
+    J::Change: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            exit: self.exit.clone(),
+            transcript: self.transcript.clone(),
+        }
+    }
+}
+impl<J: Judge, E> PartialEq for Recorded<J, E>
+where
+    Exit<J, E>: PartialEq,
+    J::Change: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.exit == other.exit && self.transcript == other.transcript
+    }
+}
+impl<J: Judge, E> Eq for Recorded<J, E>
+where
+    Exit<J, E>: Eq,
+    J::Change: Eq,
+{
+}
+
+/// Like [`judge_object`](crate::judge_object), but record a [`Transcript`] of the run.
+///
+/// Every stimulus is cloned before it's sent to the object, so
+/// the change type must be [`Clone`].
+///
+/// The judge's annotations are kept, too (see [`StepContext::annotate`](crate::StepContext::annotate)).
+///
+/// See also: [`Traced`], [`judge_traced_with`].
+pub fn judge_traced<J, O>(judge: J, object: O) -> Result<Traced<J>, J::Error>
+where
+    J: Judge,
+    J::Change: Clone,
+    O: Object<J::Change>,
+{
+    let Recorded { exit, transcript } = judge_traced_with(Config::new(), judge, object)?;
+    let outcome = exit
+        .judged()
+        .expect("simulation without limits ended without a judgment");
+    Ok(Traced {
+        outcome,
+        transcript,
+    })
+}
+
+/// Like [`judge_traced`], but within the limits of a [`Config`], and with its seed
+/// (see [`judge_with`](crate::judge_with)).
+///
+/// The transcript is kept however the simulation ends (see [`Recorded`]).
+pub fn judge_traced_with<J, O>(config: Config, judge: J, object: O) -> Result<Recorded<J>, J::Error>
+where
+    J: Judge,
+    J::Change: Clone,
+    O: Object<J::Change>,
+{
+    let mut sim = Simulation::with_config(config, judge, object);
+    let transcript = record(&mut sim, |object, msg| Ok(object.react(msg)))?;
+    Ok(Recorded {
+        exit: sim.into_exit().expect("over"),
+        transcript,
+    })
+}
+
+/// Run a simulation to the end, recording every step, and the judge's annotations.
+///
+/// `react` delivers a stimulus, like in [`Simulation::advance_by`].
+pub(crate) fn record<J, O, E>(
+    sim: &mut Simulation<J, O, E>,
+    mut react: impl FnMut(&mut O, J::Change) -> Result<Vec<J::Change>, E>,
+) -> Result<Transcript<J::Change>, J::Error>
+where
+    J: Judge,
+    J::Change: Clone,
+{
+    let mut transcript = Transcript::new();
+    sim.keep_notes(true);
+    while let Some(stimulus) = sim.advance_by(J::Change::clone, &mut react)? {
+        transcript.notes.append(&mut sim.take_notes());
+        transcript.push(stimulus, sim.pending().to_vec());
+    }
+    transcript.notes.append(&mut sim.take_notes());
+    Ok(transcript)
+}
+
+#[cfg(test)]
+mod test_trace {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::Judgment;
+
+    #[test]
+    fn test_transcript_good() {
+        let mut stack = vec![];
+        let t = judge_traced(scenario_1(), demo_impl_good(&mut stack)).unwrap();
+        assert_eq!(t.outcome.judgment, Judgment::Done);
+        assert_eq!(t.outcome.calls, t.transcript.len());
+        assert_eq!(
+            t.transcript.stimuli().copied().collect::<Vec<_>>(),
+            vec![Push(1), Push(2), Push(3), Pop, Pop, Push(4), Pop]
+        );
+        assert_eq!(t.transcript.steps[3].reactions, vec![Value(Some(3))]);
+        assert_eq!(t.transcript.steps[0].reactions, vec![]);
+    }
+
+    #[test]
+    fn test_transcript_fault() {
+        let t = judge_traced(scenario_1(), demo_impl_dumb()).unwrap();
+        assert_eq!(
+            t.outcome.judgment,
+            Judgment::Fault("too many reactions".to_string())
+        );
+        // The judge faulted right after seeing the first reaction.
+        assert_eq!(t.transcript.len(), 1);
+        assert_eq!(
+            t.transcript.steps[0],
+            Step {
+                stimulus: Push(1),
+                reactions: vec![Value(None)],
+                causes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_transcript_exhausted() {
+        let mut stack = vec![];
+        let config = Config::new().max_calls(4).seed(3);
+        let Recorded { exit, transcript } =
+            judge_traced_with(config, scenario_1(), demo_impl_good(&mut stack)).unwrap();
+        assert_eq!(
+            exit,
+            Exit::Exhausted {
+                limit: crate::Limit::Calls,
+                calls: 4,
+                reactions: 1,
+                time: 0,
+            }
+        );
+        assert_eq!(transcript.len(), 4);
+        assert_eq!(transcript.steps[3].reactions, vec![Value(Some(3))]);
+    }
+}
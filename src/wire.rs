@@ -0,0 +1,297 @@
+//! An unreliable wire between the object and the judge.
+//!
+//! The doctrines of non-immediacy and non-reliability of reactions say that
+//! reactions may be delayed, re-ordered, dropped, or duplicated on their way
+//! to the judge. Instead of hand-rolling that inside every reaction function,
+//! wrap the object in a [`Wire`]: it passes observations through untouched
+//! (those are immediate and reliable, no matter what), but mangles the reactions
+//! at random, with a seeded [`Rng`], and logs every [`Perturbation`].
+//!
+//! A judge that honors the doctrines should accept the object just the same.
+//! If it doesn't, the log tells you what the wire did to upset it.
+//!
+//! ```
+//! use caet::wire::Wire;
+//! use caet::Object;
+//!
+//! let echo = |x: i32| vec![x];
+//! let mut wire = Wire::new(echo, 7).delay_rate(1.0, 2);
+//! // Every reaction is held back for one or two calls.
+//! assert!(wire.react(1).is_empty());
+//! let late = [wire.react(2), wire.react(3)].concat();
+//! assert!(late.contains(&1));
+//! assert_eq!(wire.log().len(), 3);
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::rng::Rng;
+use crate::Object;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Something a [`Wire`] did to a reaction.
+///
+/// Calls are numbered from one; reactions are numbered from zero
+/// within the batch the object produced in that call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Perturbation {
+    /// The reaction was lost.
+    Dropped {
+        /// The call that produced it.
+        call: usize,
+        /// Its index in the batch.
+        index: usize,
+    },
+    /// The reaction was held back, to be delivered with a later batch.
+    Delayed {
+        /// The call that produced it.
+        call: usize,
+        /// Its index in the batch.
+        index: usize,
+        /// By how many calls.
+        by: usize,
+    },
+    /// The reaction was delivered twice.
+    Duplicated {
+        /// The call that produced it.
+        call: usize,
+        /// Its index in the batch.
+        index: usize,
+    },
+    /// The batch delivered in this call was shuffled.
+    Reordered {
+        /// The call.
+        call: usize,
+    },
+}
+
+/// An object behind an unreliable wire.
+///
+/// All rates are probabilities, checked for every reaction
+/// (or, for re-ordering, every batch). They all default to zero,
+/// which makes the wire perfectly reliable.
+///
+/// Order of events for each reaction: first it may be dropped; if not, it may be delayed;
+/// if not, it may be duplicated. Then, reactions whose delay is up join the batch
+/// (after the on-time ones), and then the whole batch may be shuffled.
+#[derive(Debug, Clone)]
+pub struct Wire<O, C> {
+    object: O,
+    rng: Rng,
+    drop: f64,
+    duplicate: f64,
+    delay: f64,
+    max_delay: usize,
+    reorder: f64,
+    /// Number of calls so far.
+    call: usize,
+    /// Delayed reactions, with the call at which they're due.
+    in_flight: Vec<(usize, C)>,
+    log: Vec<Perturbation>,
+}
+
+impl<O, C> Wire<O, C> {
+    /// Put the object behind a (so far, reliable) wire whose randomness starts from `seed`.
+    pub fn new(object: O, seed: u64) -> Self {
+        Self {
+            object,
+            rng: Rng::new(seed),
+            drop: 0.0,
+            duplicate: 0.0,
+            delay: 0.0,
+            max_delay: 0,
+            reorder: 0.0,
+            call: 0,
+            in_flight: Vec::new(),
+            log: Vec::new(),
+        }
+    }
+
+    /// Drop each reaction with probability `p`.
+    pub fn drop_rate(mut self, p: f64) -> Self {
+        self.drop = p;
+        self
+    }
+
+    /// Deliver each reaction twice with probability `p`.
+    pub fn duplicate_rate(mut self, p: f64) -> Self {
+        self.duplicate = p;
+        self
+    }
+
+    /// With probability `p`, hold each reaction back for 1 to `max_calls` calls
+    /// (uniformly). Does nothing if `max_calls` is zero.
+    pub fn delay_rate(mut self, p: f64, max_calls: usize) -> Self {
+        self.delay = p;
+        self.max_delay = max_calls;
+        self
+    }
+
+    /// Shuffle each delivered batch with probability `p`.
+    pub fn reorder_rate(mut self, p: f64) -> Self {
+        self.reorder = p;
+        self
+    }
+
+    /// Everything the wire has done so far, in order.
+    pub fn log(&self) -> &[Perturbation] {
+        &self.log
+    }
+
+    /// Reactions that have been delayed but not yet delivered.
+    pub fn in_flight(&self) -> impl Iterator<Item = &C> + '_ {
+        self.in_flight.iter().map(|(_, c)| c)
+    }
+
+    /// The object behind the wire.
+    pub fn object(&self) -> &O {
+        &self.object
+    }
+
+    /// Take the object and the log.
+    pub fn into_parts(self) -> (O, Vec<Perturbation>) {
+        (self.object, self.log)
+    }
+}
+
+impl<O, C> Object<C> for Wire<O, C>
+where
+    O: Object<C>,
+    C: Clone,
+{
+    fn react(&mut self, observation: C) -> Vec<C> {
+        self.call += 1;
+        let call = self.call;
+        let mut batch = Vec::new();
+        for (index, r) in self.object.react(observation).into_iter().enumerate() {
+            if self.rng.chance(self.drop) {
+                self.log.push(Perturbation::Dropped { call, index });
+            } else if self.max_delay > 0 && self.rng.chance(self.delay) {
+                let by = 1 + self.rng.below(self.max_delay);
+                self.log.push(Perturbation::Delayed { call, index, by });
+                self.in_flight.push((call + by, r));
+            } else if self.rng.chance(self.duplicate) {
+                self.log.push(Perturbation::Duplicated { call, index });
+                batch.push(r.clone());
+                batch.push(r);
+            } else {
+                batch.push(r);
+            }
+        }
+        // Deliver whatever is due (in the order it was sent), keep the rest.
+        let mut i = 0;
+        while i < self.in_flight.len() {
+            if self.in_flight[i].0 <= call {
+                batch.push(self.in_flight.remove(i).1);
+            } else {
+                i += 1;
+            }
+        }
+        if batch.len() > 1 && self.rng.chance(self.reorder) {
+            self.log.push(Perturbation::Reordered { call });
+            self.rng.shuffle(&mut batch);
+        }
+        batch
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.object.name()
+    }
+
+    /// Reset the object, forget what's in flight and the log,
+    /// and restart the randomness from the seed.
+    fn reset(&mut self) {
+        self.object.reset();
+        self.rng = Rng::new(self.rng.seed());
+        self.call = 0;
+        self.in_flight.clear();
+        self.log.clear();
+    }
+
+    fn describe_state(&self) -> Option<String> {
+        self.object.describe_state()
+    }
+}
+
+#[cfg(test)]
+mod test_wire {
+    use super::*;
+    use crate::test_stack::*;
+    use crate::{judge_object, Judgment};
+
+    fn good() -> impl FnMut(StackChange) -> Vec<StackChange> {
+        let mut stack = vec![];
+        move |msg| demo_impl_good(&mut stack)(msg)
+    }
+
+    #[test]
+    fn test_reliable() {
+        let o = judge_object(scenario_1(), Wire::new(good(), 0)).unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+    }
+
+    #[test]
+    fn test_drop() {
+        let mut wire = Wire::new(good(), 0).drop_rate(1.0);
+        wire.react(Push(1));
+        assert_eq!(wire.react(Pop), vec![]);
+        assert_eq!(wire.log(), [Perturbation::Dropped { call: 2, index: 0 }]);
+        // The stack judge tolerates silence.
+        let o = judge_object(scenario_1(), wire).unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+    }
+
+    #[test]
+    fn test_delay() {
+        let mut wire = Wire::new(good(), 0).delay_rate(1.0, 1);
+        wire.react(Push(1));
+        assert_eq!(wire.react(Pop), vec![]);
+        assert_eq!(wire.in_flight().collect::<Vec<_>>(), [&Value(Some(1))]);
+        assert_eq!(wire.react(Push(2)), vec![Value(Some(1))]);
+        assert_eq!(wire.react(Pop), vec![]);
+        assert_eq!(wire.in_flight().collect::<Vec<_>>(), [&Value(Some(2))]);
+        assert_eq!(wire.log().len(), 2);
+        // Resetting forgets what's in flight, and the log.
+        wire.reset();
+        assert_eq!(wire.in_flight().count(), 0);
+        assert!(wire.log().is_empty());
+        // The stack judge tolerates delays, too.
+        let wire = Wire::new(good(), 0).delay_rate(1.0, 1);
+        let o = judge_object(scenario_1(), wire).unwrap();
+        assert_eq!(o.judgment, Judgment::Done);
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let wire = Wire::new(good(), 0).duplicate_rate(1.0);
+        // But not duplicates.
+        let o = judge_object(scenario_1(), wire).unwrap();
+        assert_eq!(
+            o.judgment,
+            Judgment::Fault("too many reactions".to_string())
+        );
+    }
+
+    #[test]
+    fn test_reorder_deterministic() {
+        let run = |seed| {
+            let mut wire = Wire::new(|x: u32| (0..x).collect(), seed).reorder_rate(0.5);
+            let out: Vec<_> = (0..20).map(|x| wire.react(x)).collect();
+            (out, wire.into_parts().1)
+        };
+        let (a, log) = run(99);
+        assert_eq!((a.clone(), log.clone()), run(99));
+        assert!(!log.is_empty());
+        assert!(log
+            .iter()
+            .all(|p| matches!(p, Perturbation::Reordered { .. })));
+        for (x, mut batch) in a.into_iter().enumerate() {
+            batch.sort();
+            assert_eq!(batch, (0..x as u32).collect::<Vec<_>>());
+        }
+    }
+}